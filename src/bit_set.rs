@@ -0,0 +1,149 @@
+const WORD_BITS: usize = 64;
+const WORD_SHIFT: usize = 6;
+
+/// A set of usize values stored as a hierarchy of bit layers.  The lowest layer has one bit per
+/// value, and every layer above it has one bit per word of the layer below, which is set when that
+/// word is non-zero.  Finding the next value in the set skips over empty regions 64 words at a
+/// time per layer, so scanning a sparsely populated set is fast even when the values are large.
+#[derive(Clone)]
+pub struct HierarchicalBitSet {
+    layers: Vec<Vec<u64>>,
+    len: usize,
+}
+
+impl Default for HierarchicalBitSet {
+    fn default() -> HierarchicalBitSet {
+        HierarchicalBitSet {
+            layers: vec![Vec::new()],
+            len: 0,
+        }
+    }
+}
+
+impl HierarchicalBitSet {
+    pub fn new() -> HierarchicalBitSet {
+        HierarchicalBitSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// A bound on the values in the set, which only grows as values are inserted.
+    pub fn capacity(&self) -> usize {
+        self.layers[0].len() << WORD_SHIFT
+    }
+
+    pub fn clear(&mut self) {
+        *self = HierarchicalBitSet::default();
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        self.layers[0]
+            .get(value >> WORD_SHIFT)
+            .is_some_and(|&word| word & bit(value) != 0)
+    }
+
+    /// Returns true if the value was not already present in the set.
+    pub fn insert(&mut self, value: usize) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+        self.grow((value >> WORD_SHIFT) + 1);
+
+        let mut pos = value;
+        for layer in &mut self.layers {
+            let word = &mut layer[pos >> WORD_SHIFT];
+            let was_empty = *word == 0;
+            *word |= bit(pos);
+            if !was_empty {
+                break;
+            }
+            pos >>= WORD_SHIFT;
+        }
+
+        self.len += 1;
+        true
+    }
+
+    /// Returns true if the value was present in the set.
+    pub fn remove(&mut self, value: usize) -> bool {
+        if !self.contains(value) {
+            return false;
+        }
+
+        let mut pos = value;
+        for layer in &mut self.layers {
+            let word = &mut layer[pos >> WORD_SHIFT];
+            *word &= !bit(pos);
+            if *word != 0 {
+                break;
+            }
+            pos >>= WORD_SHIFT;
+        }
+
+        self.len -= 1;
+        true
+    }
+
+    /// Returns the smallest value in the set that is equal to or greater than the given value.
+    pub fn next(&self, from: usize) -> Option<usize> {
+        self.next_in_layer(0, from)
+    }
+
+    fn next_in_layer(&self, level: usize, from: usize) -> Option<usize> {
+        let layer = &self.layers[level];
+        let word = from >> WORD_SHIFT;
+        if word >= layer.len() {
+            return None;
+        }
+
+        let masked = layer[word] & (!0 << (from % WORD_BITS));
+        if masked != 0 {
+            return Some((word << WORD_SHIFT) | masked.trailing_zeros() as usize);
+        }
+
+        let next_word = if level + 1 < self.layers.len() {
+            self.next_in_layer(level + 1, word + 1)?
+        } else {
+            (word + 1..layer.len()).find(|&w| layer[w] != 0)?
+        };
+        Some((next_word << WORD_SHIFT) | layer[next_word].trailing_zeros() as usize)
+    }
+
+    // Makes sure the lowest layer has at least the given number of words, and adds layers on top
+    // until the top layer is a single word.
+    fn grow(&mut self, words: usize) {
+        let mut len = words;
+        let mut level = 0;
+        loop {
+            if self.layers[level].len() < len {
+                self.layers[level].resize(len, 0);
+            }
+            if self.layers[level].len() <= 1 {
+                break;
+            }
+
+            len = (self.layers[level].len() + WORD_BITS - 1) >> WORD_SHIFT;
+            if level + 1 == self.layers.len() {
+                let mut upper = vec![0; len];
+                for (w, &word) in self.layers[level].iter().enumerate() {
+                    if word != 0 {
+                        upper[w >> WORD_SHIFT] |= bit(w);
+                    }
+                }
+                self.layers.push(upper);
+            }
+            level += 1;
+        }
+    }
+}
+
+#[inline]
+fn bit(value: usize) -> u64 {
+    1 << (value % WORD_BITS)
+}
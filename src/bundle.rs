@@ -0,0 +1,227 @@
+use std::any::{type_name, TypeId};
+use std::borrow::Cow;
+
+use component::Component;
+use ecs::{ComponentInsertResult, Ecs};
+use entity::Entity;
+use error::Error;
+
+/// A statically typed set of components that are inserted into or removed from an entity together,
+/// without going through an `AnyMap`.  Implemented for tuples of components, and can be
+/// implemented for structs of components with `impl_bundle!`.
+///
+/// A bundle that contains the same component type more than once is rejected with
+/// `Error::DuplicateComponent`.
+pub trait Bundle: 'static + Send + Sized + BundleComponents {
+    /// The components overwritten by `insert`, which for a tuple of components is a tuple of an
+    /// `Option` of each component.
+    type Replaced;
+
+    /// Inserts every component into the given entity and returns the components that were
+    /// overwritten.  If the entity is dead, or any of the component types are unregistered or
+    /// poisoned, returns an error without inserting any of them.
+    fn insert(self, ecs: &mut Ecs, entity: Entity) -> Result<Self::Replaced, Error>;
+
+    /// Returns true if the given entity is live and has every component in the bundle.  Component
+    /// types that are unregistered are never contained.
+    fn contains(ecs: &Ecs, entity: Entity) -> Result<bool, Error>;
+
+    /// Removes and returns every component in the bundle, only if the entity has all of them.  If
+    /// any of the component types are poisoned, returns an error without removing any of them.
+    fn remove(ecs: &mut Ecs, entity: Entity) -> Result<Option<Self>, Error>;
+}
+
+/// The checks that `Bundle` makes before changing anything, implemented by `impl_bundle!`.
+#[doc(hidden)]
+pub trait BundleComponents {
+    /// Returns the name of the first component type that is in the bundle more than once, if any.
+    fn duplicate() -> Option<&'static str>;
+
+    /// Returns the names of every component type in the bundle that is not registered in the `Ecs`.
+    fn unregistered(ecs: &Ecs) -> Vec<&'static str>;
+
+    /// Returns the name of the first component type in the bundle whose storage is poisoned, if
+    /// any.
+    fn poisoned(ecs: &Ecs) -> Option<&'static str>;
+}
+
+/// Implements `Bundle` for a struct whose fields are all components, by listing the struct's
+/// fields and their types.
+///
+/// ```ignore
+/// struct Body {
+///     position: Position,
+///     velocity: Velocity,
+/// }
+///
+/// impl_bundle!(Body { position: Position, velocity: Velocity });
+/// ```
+#[macro_export]
+macro_rules! impl_bundle {
+    ($bundle:ident { $($field:ident: $component:ty),* $(,)* }) => {
+        impl $crate::bundle::BundleComponents for $bundle {
+            fn duplicate() -> Option<&'static str> {
+                <($($component,)*) as $crate::bundle::BundleComponents>::duplicate()
+            }
+
+            fn unregistered(ecs: &$crate::ecs::Ecs) -> Vec<&'static str> {
+                <($($component,)*) as $crate::bundle::BundleComponents>::unregistered(ecs)
+            }
+
+            fn poisoned(ecs: &$crate::ecs::Ecs) -> Option<&'static str> {
+                <($($component,)*) as $crate::bundle::BundleComponents>::poisoned(ecs)
+            }
+        }
+
+        impl $crate::bundle::Bundle for $bundle {
+            type Replaced = <($($component,)*) as $crate::bundle::Bundle>::Replaced;
+
+            fn insert(
+                self,
+                ecs: &mut $crate::ecs::Ecs,
+                entity: $crate::entity::Entity,
+            ) -> Result<Self::Replaced, $crate::error::Error> {
+                $crate::bundle::Bundle::insert(($(self.$field,)*), ecs, entity)
+            }
+
+            fn contains(
+                ecs: &$crate::ecs::Ecs,
+                entity: $crate::entity::Entity,
+            ) -> Result<bool, $crate::error::Error> {
+                <($($component,)*) as $crate::bundle::Bundle>::contains(ecs, entity)
+            }
+
+            fn remove(
+                ecs: &mut $crate::ecs::Ecs,
+                entity: $crate::entity::Entity,
+            ) -> Result<Option<Self>, $crate::error::Error> {
+                Ok(
+                    <($($component,)*) as $crate::bundle::Bundle>::remove(ecs, entity)?
+                        .map(|($($field,)*)| $bundle { $($field,)* }),
+                )
+            }
+        }
+    };
+}
+
+// Returns `Error::DuplicateComponent` if the bundle contains a component type more than once.
+fn check_duplicate<B: Bundle>() -> Result<(), Error> {
+    match B::duplicate() {
+        Some(type_name) => Err(Error::DuplicateComponent { type_name }),
+        None => Ok(()),
+    }
+}
+
+// Returns an error if the bundle could not be inserted without inserting only some of it.
+fn check_registered<B: Bundle>(ecs: &Ecs) -> Result<(), Error> {
+    check_duplicate::<B>()?;
+    let type_names = B::unregistered(ecs);
+    if !type_names.is_empty() {
+        return Err(Error::UnregisteredComponent {
+            type_names: type_names.into_iter().map(Cow::Borrowed).collect(),
+        });
+    }
+    check_poisoned::<B>(ecs)
+}
+
+fn check_poisoned<B: Bundle>(ecs: &Ecs) -> Result<(), Error> {
+    match B::poisoned(ecs) {
+        Some(type_name) => Err(Error::LockPoisoned { type_name }),
+        None => Ok(()),
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($component:ident)*) => (
+        impl<$($component,)*> BundleComponents for ($($component,)*)
+            where $($component: Component,)*
+        {
+            fn duplicate() -> Option<&'static str> {
+                let mut type_ids = Vec::new();
+                $(if type_ids.contains(&TypeId::of::<$component>()) {
+                    return Some(type_name::<$component>());
+                }
+                type_ids.push(TypeId::of::<$component>());)*
+                None
+            }
+
+            fn unregistered(ecs: &Ecs) -> Vec<&'static str> {
+                let mut type_names = Vec::new();
+                $(if !ecs.is_registered::<$component>() {
+                    type_names.push(type_name::<$component>());
+                })*
+                type_names
+            }
+
+            fn poisoned(ecs: &Ecs) -> Option<&'static str> {
+                $(if ecs.is_component_poisoned::<$component>() {
+                    return Some(type_name::<$component>());
+                })*
+                None
+            }
+        }
+
+        impl<$($component,)*> Bundle for ($($component,)*)
+            where $($component: Component,)*
+        {
+            type Replaced = ($(Option<$component>,)*);
+
+            #[allow(non_snake_case)]
+            fn insert(self, ecs: &mut Ecs, entity: Entity) -> Result<Self::Replaced, Error> {
+                check_registered::<Self>(ecs)?;
+                if !ecs.entity_is_live(entity) {
+                    return Err(Error::EntityDead(entity));
+                }
+                let ($($component,)*) = self;
+                Ok(($(match ecs.get_mut_component::<$component>()?.insert(entity, $component) {
+                    ComponentInsertResult::Inserted => None,
+                    ComponentInsertResult::Updated(old) => Some(old),
+                    ComponentInsertResult::EntityIsDead(_) => return Err(Error::EntityDead(entity)),
+                },)*))
+            }
+
+            fn contains(ecs: &Ecs, entity: Entity) -> Result<bool, Error> {
+                $(match ecs.read_component::<$component>() {
+                    Ok(components) => {
+                        if !components.contains(entity).unwrap_or(false) {
+                            return Ok(false);
+                        }
+                    }
+                    Err(Error::UnregisteredComponent { .. }) => return Ok(false),
+                    Err(err) => return Err(err),
+                })*
+                Ok(true)
+            }
+
+            fn remove(ecs: &mut Ecs, entity: Entity) -> Result<Option<Self>, Error> {
+                check_duplicate::<Self>()?;
+                check_poisoned::<Self>(ecs)?;
+                if !Self::contains(ecs, entity)? {
+                    return Ok(None);
+                }
+
+                Ok(Some(($(ecs
+                    .get_mut_component::<$component>()?
+                    .remove(entity)?
+                    .expect("bundle component missing"),)*)))
+            }
+        }
+    );
+}
+
+impl_tuple! {A}
+impl_tuple! {A B}
+impl_tuple! {A B C}
+impl_tuple! {A B C D}
+impl_tuple! {A B C D E}
+impl_tuple! {A B C D E F}
+impl_tuple! {A B C D E F G}
+impl_tuple! {A B C D E F G H}
+impl_tuple! {A B C D E F G H I}
+impl_tuple! {A B C D E F G H I J}
+impl_tuple! {A B C D E F G H I J K}
+impl_tuple! {A B C D E F G H I J K L}
+impl_tuple! {A B C D E F G H I J K L M}
+impl_tuple! {A B C D E F G H I J K L M N}
+impl_tuple! {A B C D E F G H I J K L M N O}
+impl_tuple! {A B C D E F G H I J K L M N O P}
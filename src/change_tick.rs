@@ -0,0 +1,271 @@
+use std::ops::{Deref, DerefMut, Range};
+
+use component::{Component, ComponentStorage};
+use component_scanner::ComponentScanner;
+use parallel_scan::SplitScanner;
+
+/// The world change tick at which a component was added and at which it was last changed.  A tick
+/// of 0 means never, and adding a component also counts as changing it.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ComponentTicks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+/// A component storage that keeps the change ticks of its components in a storage with the same
+/// layout, so that the ticks are exactly as sparse as the components.
+pub trait TickedStorage {
+    type Ticks: for<'a> ComponentStorage<'a, Component = ComponentTicks>;
+}
+
+/// The storage for the change ticks of the component `T`.
+pub type TicksOf<T> = <<T as Component>::Storage as TickedStorage>::Ticks;
+
+/// The scanner returned by `scan_mut` for the component `T`, which tracks changes.
+pub type TrackedScanMut<'a, T> = ChangeTrackingScanner<
+    'a,
+    <<T as Component>::Storage as ComponentStorage<'a>>::ScanMut,
+    TicksOf<T>,
+>;
+
+/// Records the change ticks of every component in a component storage, in a tick storage `S` that
+/// has a tick for exactly the indexes that have a component.
+///
+/// Components are only known to be changed when they are accessed mutably, so every component
+/// returned by `get_mut` is marked as changed, and so is every component from `scan_mut` once it is
+/// mutably dereferenced, whether or not it is actually modified.
+#[derive(Clone, Default)]
+pub struct ChangeTicks<S>(S);
+
+/// Wraps a mutable component scanner, returning each component along with its change ticks.
+pub struct ChangeTrackingScanner<'a, S, T: ComponentStorage<'a>> {
+    scan: S,
+    ticks: T::ScanMut,
+    tick: u64,
+}
+
+/// A component returned by a `ChangeTrackingScanner`, which is only marked as changed once it is
+/// mutably dereferenced.  Components that a join skips, or that are only read, stay unchanged.
+pub struct TrackedMut<'a, T: 'a> {
+    component: &'a mut T,
+    ticks: &'a mut ComponentTicks,
+    tick: u64,
+}
+
+/// Filters a component scanner to the components whose tick is greater than a given tick.
+pub struct TickSinceScanner<'a, S, T: 'a> {
+    scan: S,
+    ticks: &'a T,
+    since: u64,
+    tick: fn(&ComponentTicks) -> u64,
+}
+
+impl<S: for<'a> ComponentStorage<'a, Component = ComponentTicks>> ChangeTicks<S> {
+    pub fn new() -> ChangeTicks<S> {
+        ChangeTicks(S::default())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&ComponentTicks> {
+        self.0.get(index)
+    }
+
+    pub fn added(&self, index: usize) -> u64 {
+        self.get(index).map_or(0, |ticks| ticks.added)
+    }
+
+    pub fn changed(&self, index: usize) -> u64 {
+        self.get(index).map_or(0, |ticks| ticks.changed)
+    }
+
+    pub fn set_added(&mut self, index: usize, tick: u64) {
+        self.0.insert(
+            index,
+            ComponentTicks {
+                added: tick,
+                changed: tick,
+            },
+        );
+    }
+
+    /// Does nothing if there is no component at the given index.
+    pub fn set_changed(&mut self, index: usize, tick: u64) {
+        if let Some(ticks) = self.0.get_mut(index) {
+            ticks.changed = tick;
+        }
+    }
+
+    pub fn clear(&mut self, index: usize) {
+        self.0.remove(index);
+    }
+
+    pub fn scan_added_since<C: ComponentScanner>(
+        &self,
+        scan: C,
+        since: u64,
+    ) -> TickSinceScanner<'_, C, S> {
+        TickSinceScanner {
+            scan,
+            ticks: &self.0,
+            since,
+            tick: |ticks| ticks.added,
+        }
+    }
+
+    pub fn scan_changed_since<C: ComponentScanner>(
+        &self,
+        scan: C,
+        since: u64,
+    ) -> TickSinceScanner<'_, C, S> {
+        TickSinceScanner {
+            scan,
+            ticks: &self.0,
+            since,
+            tick: |ticks| ticks.changed,
+        }
+    }
+
+    pub fn track_changes<'a, C: ComponentScanner>(
+        &'a mut self,
+        scan: C,
+        tick: u64,
+    ) -> ChangeTrackingScanner<'a, C, S> {
+        ChangeTrackingScanner {
+            scan,
+            ticks: self.0.scan_mut(),
+            tick,
+        }
+    }
+}
+
+impl<'a, C: 'a, S, T> ComponentScanner for ChangeTrackingScanner<'a, S, T>
+where
+    S: ComponentScanner<Item = &'a mut C>,
+    T: ComponentStorage<'a, Component = ComponentTicks>,
+{
+    type Item = TrackedMut<'a, C>;
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(TrackedMut<'a, C>, usize)> {
+        let (component, index) = self.scan.scan(until)?;
+        let ticks = match self.ticks.scan(Some(index)) {
+            Some((ticks, ticks_index)) if ticks_index == index => ticks,
+            _ => panic!("component at index {} has no change ticks", index),
+        };
+        let tracked = TrackedMut {
+            component,
+            ticks,
+            tick: self.tick,
+        };
+        Some((tracked, index))
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        self.scan.estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scan.is_bounded()
+    }
+}
+
+impl<'a, C: 'a, S, T> SplitScanner for ChangeTrackingScanner<'a, S, T>
+where
+    S: SplitScanner<Item = &'a mut C>,
+    T: ComponentStorage<'a, Component = ComponentTicks>,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.scan.index_range()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower_scan, upper_scan) = self.scan.split_at(index);
+        let (lower_ticks, upper_ticks) = self.ticks.split_at(index);
+        let lower = ChangeTrackingScanner {
+            scan: lower_scan,
+            ticks: lower_ticks,
+            tick: self.tick,
+        };
+        let upper = ChangeTrackingScanner {
+            scan: upper_scan,
+            ticks: upper_ticks,
+            tick: self.tick,
+        };
+        (lower, upper)
+    }
+}
+
+impl<'a, T> TrackedMut<'a, T> {
+    /// Marks the component as changed and returns a reference that lives as long as the scan.
+    pub fn into_mut(self) -> &'a mut T {
+        self.ticks.changed = self.tick;
+        self.component
+    }
+}
+
+impl<'a, T> Deref for TrackedMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.component
+    }
+}
+
+impl<'a, T> DerefMut for TrackedMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.ticks.changed = self.tick;
+        self.component
+    }
+}
+
+impl<'a, S, T> ComponentScanner for TickSinceScanner<'a, S, T>
+where
+    S: ComponentScanner,
+    T: for<'b> ComponentStorage<'b, Component = ComponentTicks>,
+{
+    type Item = S::Item;
+
+    fn scan(&mut self, mut until: Option<usize>) -> Option<(S::Item, usize)> {
+        loop {
+            let (item, index) = self.scan.scan(until)?;
+            if self
+                .ticks
+                .get(index)
+                .is_some_and(|ticks| (self.tick)(ticks) > self.since)
+            {
+                return Some((item, index));
+            }
+            until = Some(index + 1);
+        }
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        self.scan.estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scan.is_bounded()
+    }
+}
+
+impl<'a, S, T> SplitScanner for TickSinceScanner<'a, S, T>
+where
+    S: SplitScanner,
+    T: for<'b> ComponentStorage<'b, Component = ComponentTicks>,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.scan.index_range()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower, upper) = self.scan.split_at(index);
+        (
+            TickSinceScanner {
+                scan: lower,
+                ..self
+            },
+            TickSinceScanner {
+                scan: upper,
+                ..self
+            },
+        )
+    }
+}
@@ -0,0 +1,180 @@
+use std::any::{type_name, TypeId};
+use std::mem;
+use std::sync::Mutex;
+
+use anymap::AnyMap;
+
+use component::Component;
+use entity::Entity;
+use error::Error;
+use world::World;
+
+type Command = Box<dyn FnOnce(&mut World) -> Result<(), Error> + Send>;
+type ComponentInsert = Box<dyn FnOnce(&mut AnyMap) + Send>;
+
+/// Records structural changes to a `World` that cannot be made while the `World` is shared, such as
+/// while holding handles from `multi_lock` or while running systems.  Commands can be recorded
+/// through a shared reference from any number of threads, and are applied in the order they were
+/// recorded by `World::apply_commands`.
+///
+/// When applied, commands that target an entity that is dead by then (including an entity removed
+/// by an earlier command in the same buffer) do nothing.  A command that fails, such as inserting
+/// an unregistered component, does not stop the remaining commands from being applied.
+#[derive(Default)]
+pub struct CommandBuffer(Mutex<Vec<Command>>);
+
+impl CommandBuffer {
+    pub fn new() -> CommandBuffer {
+        CommandBuffer::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// Reserves a new entity in the given `World` and returns it immediately, so that later
+    /// commands can refer to it.  The entity becomes live (with no components) at the next
+    /// structural change to the world, even if this buffer is never applied.
+    pub fn spawn(&self, world: &World) -> Entity {
+        world.reserve_entity()
+    }
+
+    /// Starts building a new entity whose components are all inserted by a single command, see
+    /// `DeferredEntityBuilder`.
+    pub fn create_entity<'a>(&'a self, world: &World) -> DeferredEntityBuilder<'a> {
+        DeferredEntityBuilder {
+            commands: self,
+            entity: self.spawn(world),
+            components: Vec::new(),
+            type_names: Vec::new(),
+        }
+    }
+
+    pub fn remove_entity(&self, entity: Entity) {
+        self.push(move |world| {
+            world.remove_entity(entity);
+            Ok(())
+        });
+    }
+
+    pub fn insert_component<T: Component>(&self, entity: Entity, component: T) {
+        self.push(move |world| {
+            world.get_mut_component::<T>()?.insert(entity, component);
+            Ok(())
+        });
+    }
+
+    pub fn remove_component<T: Component>(&self, entity: Entity) {
+        self.push(move |world| {
+            let _ = world.get_mut_component::<T>()?.remove(entity);
+            Ok(())
+        });
+    }
+
+    pub fn insert_resource<T: 'static + Send + Sync>(&self, resource: T) {
+        self.push(move |world| {
+            world.insert_resource(resource);
+            Ok(())
+        });
+    }
+
+    pub fn remove_resource<T: 'static + Send + Sync>(&self) {
+        self.push(move |world| {
+            world.remove_resource::<T>();
+            Ok(())
+        });
+    }
+
+    /// Records an arbitrary change to the world.  Errors of the command's own can be returned with
+    /// `Error::command`.
+    pub fn push<F>(&self, command: F)
+    where
+        F: 'static + FnOnce(&mut World) -> Result<(), Error> + Send,
+    {
+        self.0.lock().unwrap().push(Box::new(command));
+    }
+
+    pub(crate) fn take(&self) -> Vec<Command> {
+        mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// Collects components for an entity spawned in a `CommandBuffer`.  `build` records a single command
+/// that inserts all of the components, so if any of them are unregistered, none of them are
+/// inserted and the spawned entity is removed again.
+pub struct DeferredEntityBuilder<'a> {
+    commands: &'a CommandBuffer,
+    entity: Entity,
+    components: Vec<ComponentInsert>,
+    type_names: Vec<(TypeId, &'static str)>,
+}
+
+impl<'a> DeferredEntityBuilder<'a> {
+    pub fn with<T: Component>(mut self, component: T) -> Self {
+        self.components.push(Box::new(move |components| {
+            components.insert(component);
+        }));
+        self.type_names.push((TypeId::of::<T>(), type_name::<T>()));
+        self
+    }
+
+    pub fn with_default<T: Component + Default>(self) -> Self {
+        self.with(T::default())
+    }
+
+    pub fn maybe_with<T: Component>(self, component: Option<T>) -> Self {
+        match component {
+            Some(component) => self.with(component),
+            None => self,
+        }
+    }
+
+    /// Records the command and returns the spawned entity.
+    pub fn build(self) -> Entity {
+        let entity = self.entity;
+        let inserts = self.components;
+        let type_names = self.type_names;
+        self.commands.push(move |world| {
+            if !world.entity_is_live(entity) {
+                return Ok(());
+            }
+
+            let mut components = AnyMap::new();
+            for insert in inserts {
+                insert(&mut components);
+            }
+            if let Err(err) = world.insert_named_components(entity, components, &type_names) {
+                world.remove_entity(entity);
+                return Err(err);
+            }
+            Ok(())
+        });
+        entity
+    }
+}
+
+impl World {
+    /// Applies every command in the given buffer in the order they were recorded, leaving the
+    /// buffer empty.  If any commands fail, the rest are still applied and the first error is
+    /// returned.
+    pub fn apply_commands(&mut self, commands: &CommandBuffer) -> Result<(), Error> {
+        self.run_commands(commands.take())
+    }
+
+    pub(crate) fn run_commands(&mut self, commands: Vec<Command>) -> Result<(), Error> {
+        self.allocate_reserved_entities();
+        let mut result = Ok(());
+        for command in commands {
+            if let Err(err) = command(self) {
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+}
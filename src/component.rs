@@ -1,13 +1,16 @@
-use component_scanner::ComponentScanner;
+use std::ops::Range;
+
+use change_tick::TickedStorage;
+use parallel_scan::SplitScanner;
 
 pub trait Component: 'static + Send + Sync + Sized + Clone {
-    type Storage: for<'a> ComponentStorage<'a, Component = Self>;
+    type Storage: for<'a> ComponentStorage<'a, Component = Self> + TickedStorage;
 }
 
 pub trait ComponentStorage<'a>: 'static + Send + Sync + Default + Clone {
     type Component: 'static + Send + Sync + Sized + Clone;
-    type Scan: ComponentScanner<Item = &'a Self::Component>;
-    type ScanMut: ComponentScanner<Item = &'a mut Self::Component>;
+    type Scan: SplitScanner<Item = &'a Self::Component> + Send;
+    type ScanMut: SplitScanner<Item = &'a mut Self::Component> + Send;
 
     fn get(&self, index: usize) -> Option<&Self::Component>;
     fn get_mut(&mut self, index: usize) -> Option<&mut Self::Component>;
@@ -17,4 +20,13 @@ pub trait ComponentStorage<'a>: 'static + Send + Sync + Default + Clone {
 
     fn scan(&'a self) -> Self::Scan;
     fn scan_mut(&'a mut self) -> Self::ScanMut;
+
+    /// Scans only the components whose indexes are within the given range, seeking directly to
+    /// the start of the range.
+    fn scan_range(&'a self, range: Range<usize>) -> Self::Scan;
+    fn scan_mut_range(&'a mut self, range: Range<usize>) -> Self::ScanMut;
+
+    /// An upper bound on the number of stored components, which scanners use to estimate how many
+    /// entries they will return.
+    fn estimated_len(&self) -> usize;
 }
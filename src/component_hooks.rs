@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use entity::Entity;
+
+type Hook<T> = Arc<dyn Fn(Entity, &T) + Send + Sync>;
+type ReplaceHook<T> = Arc<dyn Fn(Entity, &T, &T) + Send + Sync>;
+
+/// Callbacks for a component type, set with `Ecs::register_component_with_hooks`, that are called
+/// whenever a component of that type is inserted, replaced or removed, however it happens.  They
+/// are intended for keeping external indexes up to date.
+///
+/// Hooks are called while the component storage is locked, so they must not access the `World`
+/// they are registered with.
+pub struct ComponentHooks<T> {
+    on_insert: Option<Hook<T>>,
+    on_replace: Option<ReplaceHook<T>>,
+    on_remove: Option<Hook<T>>,
+}
+
+impl<T> Default for ComponentHooks<T> {
+    fn default() -> ComponentHooks<T> {
+        ComponentHooks {
+            on_insert: None,
+            on_replace: None,
+            on_remove: None,
+        }
+    }
+}
+
+impl<T> Clone for ComponentHooks<T> {
+    fn clone(&self) -> ComponentHooks<T> {
+        ComponentHooks {
+            on_insert: self.on_insert.clone(),
+            on_replace: self.on_replace.clone(),
+            on_remove: self.on_remove.clone(),
+        }
+    }
+}
+
+impl<T> ComponentHooks<T> {
+    pub fn new() -> ComponentHooks<T> {
+        ComponentHooks::default()
+    }
+
+    /// Called with a newly inserted component, when the entity did not already have one.
+    pub fn on_insert<F>(mut self, hook: F) -> Self
+    where
+        F: 'static + Fn(Entity, &T) + Send + Sync,
+    {
+        self.on_insert = Some(Arc::new(hook));
+        self
+    }
+
+    /// Called with the old and then the new component when an entity's component is overwritten.
+    pub fn on_replace<F>(mut self, hook: F) -> Self
+    where
+        F: 'static + Fn(Entity, &T, &T) + Send + Sync,
+    {
+        self.on_replace = Some(Arc::new(hook));
+        self
+    }
+
+    /// Called with a removed component, including when its entity is removed.
+    pub fn on_remove<F>(mut self, hook: F) -> Self
+    where
+        F: 'static + Fn(Entity, &T) + Send + Sync,
+    {
+        self.on_remove = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn inserted(&self, entity: Entity, component: &T) {
+        if let Some(hook) = &self.on_insert {
+            hook(entity, component);
+        }
+    }
+
+    pub fn replaced(&self, entity: Entity, old: &T, new: &T) {
+        if let Some(hook) = &self.on_replace {
+            hook(entity, old, new);
+        }
+    }
+
+    pub fn removed(&self, entity: Entity, component: &T) {
+        if let Some(hook) = &self.on_remove {
+            hook(entity, component);
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use std::cmp;
+use std::ops::Range;
+
+use error::Error;
+use parallel_scan::{ParallelScan, SplitScanner};
+
 #[derive(Debug, Fail)]
 pub enum ComponentSingletonError {
     #[fail(display = "no such singleton entry in scan")]
@@ -13,6 +19,20 @@ pub trait ComponentScanner {
     /// greater than the given index, then return the item and index of that entry, if it exists.
     fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)>;
 
+    /// An estimate of how many more entries this scanner will return, used to decide which
+    /// scanner drives a join.  Should be an upper bound where possible, and None if unknown or
+    /// unbounded.
+    fn estimated_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether this scanner eventually stops returning entries.  Scanners made with
+    /// `opt_until(usize::MAX)` or `Without` query terms return entries for every index and so are
+    /// unbounded.
+    fn is_bounded(&self) -> bool {
+        true
+    }
+
     fn iter(self) -> ComponentScannerIterator<Self>
     where
         Self: Sized,
@@ -20,6 +40,23 @@ pub trait ComponentScanner {
         ComponentScannerIterator(self)
     }
 
+    /// Processes the scanned items on multiple threads, see `ParallelScan`.
+    fn par_scan(self) -> ParallelScan<Self>
+    where
+        Self: SplitScanner,
+    {
+        ParallelScan::new(self)
+    }
+
+    /// Calls the given function with every scanned item on multiple threads, in no particular order.
+    fn par_for_each<F>(self, f: F)
+    where
+        Self: SplitScanner + Send,
+        F: Fn(Self::Item) + Sync,
+    {
+        self.par_scan().for_each(|item, _| f(item))
+    }
+
     /// If only a single entity should match, get the result out and error if there is not exactly
     /// one entry available.
     fn singleton(mut self) -> Result<Self::Item, ComponentSingletonError>
@@ -45,9 +82,36 @@ pub trait ComponentScanner {
         }
     }
 
-    /// Converts this ComponentScanner into one that exists for all indexes and instead returns
-    /// Some(Item) if it exists and None otherwise.
-    fn opt(mut self) -> ComponentScannerOpt<Self, Self::Item>
+    /// Skips the entries whose items do not match the given predicate.
+    fn filter<F: Fn(&Self::Item) -> bool>(self, predicate: F) -> ComponentScannerFilter<Self, F>
+    where
+        Self: Sized,
+    {
+        ComponentScannerFilter {
+            scanner: self,
+            predicate,
+        }
+    }
+
+    /// Maps the given function over the items returned by scan, skipping the entries for which it
+    /// returns None.
+    fn filter_map<R, F: Fn(Self::Item) -> Option<R>>(
+        self,
+        f: F,
+    ) -> ComponentScannerFilterMap<Self, F>
+    where
+        Self: Sized,
+    {
+        ComponentScannerFilterMap {
+            scanner: self,
+            function: f,
+        }
+    }
+
+    /// Converts this ComponentScanner into one that exists for all indexes below `end` and instead
+    /// returns Some(Item) if it exists and None otherwise.  `end` should generally be the maximum
+    /// allocated entity index, see `ComponentReadHandle::scan_opt`.
+    fn opt_until(mut self, end: usize) -> ComponentScannerOpt<Self, Self::Item>
     where
         Self: Sized,
     {
@@ -55,19 +119,17 @@ pub trait ComponentScanner {
         ComponentScannerOpt {
             scanner: self,
             current: 0,
+            end,
             next: first,
         }
     }
 
     /// Converts this ComponentScanner into one that only has values when the given scanner also
     /// has a value.
-    fn limit<T>(
-        self,
-        and: T,
-    ) -> ComponentScannerMap<
-        ComponentScannerJoin<Self, T>,
-        fn(<ComponentScannerJoin<Self, T> as ComponentScanner>::Item) -> Self::Item,
-    >
+    ///
+    /// Returns `Error::UnboundedJoin` if neither scanner ends on its own, like
+    /// `component_scan_join`.
+    fn limit<T>(self, and: T) -> Result<ComponentScannerLimit<Self, T>, Error>
     where
         Self: Sized,
         T: ComponentScanner,
@@ -76,9 +138,32 @@ pub trait ComponentScanner {
             i
         }
 
-        ComponentScannerMap {
-            scanner: ComponentScannerJoin(self, and),
+        bounded(ComponentScannerMap {
+            scanner: ComponentScannerJoin::new(self, and),
             function: fst as fn((Self::Item, T::Item)) -> Self::Item,
+        })
+    }
+
+    /// Combines this ComponentScanner with another, producing a scanner that has values for every
+    /// index where either of them has a value.
+    fn or<T>(self, or: T) -> ComponentScannerUnion<Self, T>
+    where
+        Self: Sized,
+        T: ComponentScanner,
+    {
+        ComponentScannerUnion(UnionSide::new(self), UnionSide::new(or))
+    }
+
+    /// Restricts this ComponentScanner to the indexes within the given range.  The first scan
+    /// seeks directly to the start of the range, but for a storage scanner, using
+    /// `ComponentStorage::scan_range` instead is generally faster.
+    fn range(self, range: Range<usize>) -> ComponentScannerRange<Self>
+    where
+        Self: Sized,
+    {
+        ComponentScannerRange {
+            scanner: self,
+            range,
         }
     }
 
@@ -93,7 +178,7 @@ pub trait ComponentScanner {
         ComponentScannerNot {
             scanner: self,
             not_scanner: not,
-            next_not: next_not,
+            next_not,
         }
     }
 }
@@ -104,6 +189,14 @@ impl<T: ComponentScanner + ?Sized> ComponentScanner for Box<T> {
     fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
         (**self).scan(until)
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        (**self).estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        (**self).is_bounded()
+    }
 }
 
 pub struct ComponentScannerIterator<T>(T);
@@ -119,6 +212,16 @@ where
     }
 }
 
+pub type ComponentScannerLimit<T1, T2> = ComponentScannerMap<
+    ComponentScannerJoin<T1, T2>,
+    fn(
+        (
+            <T1 as ComponentScanner>::Item,
+            <T2 as ComponentScanner>::Item,
+        ),
+    ) -> <T1 as ComponentScanner>::Item,
+>;
+
 pub struct ComponentScannerMap<T, F> {
     scanner: T,
     function: F,
@@ -136,6 +239,145 @@ where
             .scan(until)
             .map(|(item, id)| ((self.function)(item), id))
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        self.scanner.estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scanner.is_bounded()
+    }
+}
+
+impl<T, F, R> SplitScanner for ComponentScannerMap<T, F>
+where
+    T: SplitScanner,
+    F: Fn(T::Item) -> R + Clone,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.scanner.index_range()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower, upper) = self.scanner.split_at(index);
+        let lower = ComponentScannerMap {
+            scanner: lower,
+            function: self.function.clone(),
+        };
+        let upper = ComponentScannerMap {
+            scanner: upper,
+            function: self.function,
+        };
+        (lower, upper)
+    }
+}
+
+pub struct ComponentScannerFilter<T, F> {
+    scanner: T,
+    predicate: F,
+}
+
+impl<T, F> ComponentScanner for ComponentScannerFilter<T, F>
+where
+    T: ComponentScanner,
+    F: Fn(&T::Item) -> bool,
+{
+    type Item = T::Item;
+
+    fn scan(&mut self, mut until: Option<usize>) -> Option<(Self::Item, usize)> {
+        loop {
+            let (item, index) = self.scanner.scan(until)?;
+            if (self.predicate)(&item) {
+                return Some((item, index));
+            }
+            until = Some(index + 1);
+        }
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        self.scanner.estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scanner.is_bounded()
+    }
+}
+
+impl<T, F> SplitScanner for ComponentScannerFilter<T, F>
+where
+    T: SplitScanner,
+    F: Fn(&T::Item) -> bool + Clone,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.scanner.index_range()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower, upper) = self.scanner.split_at(index);
+        let lower = ComponentScannerFilter {
+            scanner: lower,
+            predicate: self.predicate.clone(),
+        };
+        let upper = ComponentScannerFilter {
+            scanner: upper,
+            predicate: self.predicate,
+        };
+        (lower, upper)
+    }
+}
+
+pub struct ComponentScannerFilterMap<T, F> {
+    scanner: T,
+    function: F,
+}
+
+impl<T, F, R> ComponentScanner for ComponentScannerFilterMap<T, F>
+where
+    T: ComponentScanner,
+    F: Fn(T::Item) -> Option<R>,
+{
+    type Item = R;
+
+    fn scan(&mut self, mut until: Option<usize>) -> Option<(Self::Item, usize)> {
+        loop {
+            let (item, index) = self.scanner.scan(until)?;
+            if let Some(item) = (self.function)(item) {
+                return Some((item, index));
+            }
+            until = Some(index + 1);
+        }
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        self.scanner.estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scanner.is_bounded()
+    }
+}
+
+impl<T, F, R> SplitScanner for ComponentScannerFilterMap<T, F>
+where
+    T: SplitScanner,
+    F: Fn(T::Item) -> Option<R> + Clone,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.scanner.index_range()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower, upper) = self.scanner.split_at(index);
+        let lower = ComponentScannerFilterMap {
+            scanner: lower,
+            function: self.function.clone(),
+        };
+        let upper = ComponentScannerFilterMap {
+            scanner: upper,
+            function: self.function,
+        };
+        (lower, upper)
+    }
 }
 
 pub struct ComponentScannerNot<T1, T2> {
@@ -173,11 +415,111 @@ where
             }
         }
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        self.scanner.estimated_len()
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scanner.is_bounded()
+    }
+}
+
+impl<T1, T2, I> SplitScanner for ComponentScannerNot<T1, T2>
+where
+    T1: SplitScanner<Item = I>,
+    T2: SplitScanner,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.scanner.index_range()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower, upper) = self.scanner.split_at(index);
+        let (lower_not, mut upper_not) = self.not_scanner.split_at(index);
+        // The upper half needs its own next excluded index if the current one is below the split,
+        // and otherwise the lower half has no excluded indexes left.
+        let (lower_next_not, upper_next_not) = match self.next_not {
+            Some(next_not) if next_not < index => {
+                (Some(next_not), upper_not.scan(None).map(|(_, ind)| ind))
+            }
+            next_not => (None, next_not),
+        };
+        let lower = ComponentScannerNot {
+            scanner: lower,
+            not_scanner: lower_not,
+            next_not: lower_next_not,
+        };
+        let upper = ComponentScannerNot {
+            scanner: upper,
+            not_scanner: upper_not,
+            next_not: upper_next_not,
+        };
+        (lower, upper)
+    }
+}
+
+pub struct ComponentScannerRange<T> {
+    scanner: T,
+    range: Range<usize>,
+}
+
+impl<T: ComponentScanner> ComponentScanner for ComponentScannerRange<T> {
+    type Item = T::Item;
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
+        if self.range.start >= self.range.end {
+            return None;
+        }
+
+        let until = cmp::max(self.range.start, until.unwrap_or(0));
+        match self.scanner.scan(Some(until)) {
+            Some((item, index)) if index < self.range.end => Some((item, index)),
+            _ => {
+                self.range.start = self.range.end;
+                None
+            }
+        }
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        let len = self.range.end.saturating_sub(self.range.start);
+        Some(
+            self.scanner
+                .estimated_len()
+                .map_or(len, |l| cmp::min(l, len)),
+        )
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.scanner.is_bounded() || self.range.end != usize::MAX
+    }
+}
+
+impl<T: SplitScanner> SplitScanner for ComponentScannerRange<T> {
+    fn index_range(&self) -> Range<usize> {
+        let range = self.scanner.index_range();
+        cmp::max(range.start, self.range.start)..cmp::min(range.end, self.range.end)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower, upper) = self.scanner.split_at(index);
+        let lower = ComponentScannerRange {
+            scanner: lower,
+            range: self.range.start..cmp::min(index, self.range.end),
+        };
+        let upper = ComponentScannerRange {
+            scanner: upper,
+            range: cmp::max(index, self.range.start)..self.range.end,
+        };
+        (lower, upper)
+    }
 }
 
 pub struct ComponentScannerOpt<T, I> {
     scanner: T,
     current: usize,
+    end: usize,
     next: Option<(I, usize)>,
 }
 
@@ -192,6 +534,9 @@ where
         if self.current < until {
             self.current = until;
         }
+        if self.current >= self.end {
+            return None;
+        }
 
         loop {
             if let Some((next_value, next_index)) = self.next.take() {
@@ -218,34 +563,260 @@ where
             }
         }
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        if self.is_bounded() {
+            Some(self.end.saturating_sub(self.current))
+        } else {
+            None
+        }
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.end != usize::MAX
+    }
 }
 
-pub struct ComponentScannerJoin<H, T>(H, T);
+impl<T, I> SplitScanner for ComponentScannerOpt<T, I>
+where
+    T: SplitScanner<Item = I>,
+{
+    fn index_range(&self) -> Range<usize> {
+        self.current..self.end
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = cmp::max(self.current, cmp::min(index, self.end));
+        let (lower, mut upper) = self.scanner.split_at(mid);
+        // The entry already taken from the scanner goes to the half that it is in, and the other
+        // half starts from its own scanner's first entry.
+        let (lower_next, upper_next) = match self.next {
+            Some((item, next_index)) if next_index < mid => {
+                (Some((item, next_index)), upper.scan(None))
+            }
+            next => (None, next),
+        };
+        let lower = ComponentScannerOpt {
+            scanner: lower,
+            current: self.current,
+            end: mid,
+            next: lower_next,
+        };
+        let upper = ComponentScannerOpt {
+            scanner: upper,
+            current: mid,
+            end: self.end,
+            next: upper_next,
+        };
+        (lower, upper)
+    }
+}
+
+/// Joins two scanners, driving the join from whichever of them is estimated to return fewer
+/// entries, so the other only has to seek to the indexes the driver returns.
+pub struct ComponentScannerJoin<H, T> {
+    head: H,
+    tail: T,
+    tail_drives: bool,
+}
+
+impl<H: ComponentScanner, T: ComponentScanner> ComponentScannerJoin<H, T> {
+    pub fn new(head: H, tail: T) -> ComponentScannerJoin<H, T> {
+        let tail_drives = match (head.estimated_len(), tail.estimated_len()) {
+            (Some(head_len), Some(tail_len)) => tail_len < head_len,
+            (None, Some(_)) => true,
+            _ => false,
+        };
+        ComponentScannerJoin {
+            head,
+            tail,
+            tail_drives,
+        }
+    }
+}
 
 impl<H: ComponentScanner, T: ComponentScanner> ComponentScanner for ComponentScannerJoin<H, T> {
     type Item = (H::Item, T::Item);
 
     fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
-        match (self.0.scan(until), self.1.scan(until)) {
-            (Some((mut item1, mut id1)), Some((mut item2, mut id2))) => loop {
-                if id1 < id2 {
-                    if let Some(res) = self.0.scan(Some(id2)) {
-                        item1 = res.0;
-                        id1 = res.1;
-                    } else {
-                        return None;
-                    }
-                } else if id1 > id2 {
-                    if let Some(res) = self.1.scan(Some(id1)) {
-                        item2 = res.0;
-                        id2 = res.1;
-                    } else {
-                        return None;
-                    }
-                } else {
-                    return Some(((item1, item2), id1));
+        let ((mut item1, mut id1), (mut item2, mut id2));
+        if self.tail_drives {
+            (item2, id2) = self.tail.scan(until)?;
+            (item1, id1) = self.head.scan(Some(id2))?;
+        } else {
+            (item1, id1) = self.head.scan(until)?;
+            (item2, id2) = self.tail.scan(Some(id1))?;
+        }
+
+        loop {
+            if id1 < id2 {
+                (item1, id1) = self.head.scan(Some(id2))?;
+            } else if id1 > id2 {
+                (item2, id2) = self.tail.scan(Some(id1))?;
+            } else {
+                return Some(((item1, item2), id1));
+            }
+        }
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        match (self.head.estimated_len(), self.tail.estimated_len()) {
+            (Some(head_len), Some(tail_len)) => Some(cmp::min(head_len, tail_len)),
+            (head_len, tail_len) => head_len.or(tail_len),
+        }
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.head.is_bounded() || self.tail.is_bounded()
+    }
+}
+
+impl<H: SplitScanner, T: SplitScanner> SplitScanner for ComponentScannerJoin<H, T> {
+    fn index_range(&self) -> Range<usize> {
+        let (head, tail) = (self.head.index_range(), self.tail.index_range());
+        cmp::max(head.start, tail.start)..cmp::min(head.end, tail.end)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower_head, upper_head) = self.head.split_at(index);
+        let (lower_tail, upper_tail) = self.tail.split_at(index);
+        let lower = ComponentScannerJoin {
+            head: lower_head,
+            tail: lower_tail,
+            tail_drives: self.tail_drives,
+        };
+        let upper = ComponentScannerJoin {
+            head: upper_head,
+            tail: upper_tail,
+            tail_drives: self.tail_drives,
+        };
+        (lower, upper)
+    }
+}
+
+pub struct ComponentScannerUnion<H: ComponentScanner, T: ComponentScanner>(
+    UnionSide<H>,
+    UnionSide<T>,
+);
+
+impl<H: ComponentScanner, T: ComponentScanner> ComponentScanner for ComponentScannerUnion<H, T> {
+    type Item = (Option<H::Item>, Option<T::Item>);
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
+        let index = match (self.0.peek(until), self.1.peek(until)) {
+            (Some(id1), Some(id2)) => cmp::min(id1, id2),
+            (Some(id), None) | (None, Some(id)) => id,
+            (None, None) => return None,
+        };
+        Some(((self.0.take(index), self.1.take(index)), index))
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.0.estimated_len()? + self.1.estimated_len()?)
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.0.scanner.is_bounded() && self.1.scanner.is_bounded()
+    }
+}
+
+impl<H: SplitScanner, T: SplitScanner> SplitScanner for ComponentScannerUnion<H, T> {
+    fn index_range(&self) -> Range<usize> {
+        let (head, tail) = (self.0.index_range(), self.1.index_range());
+        if head.start >= head.end {
+            tail
+        } else if tail.start >= tail.end {
+            head
+        } else {
+            cmp::min(head.start, tail.start)..cmp::max(head.end, tail.end)
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let (lower_head, upper_head) = self.0.split_at(index);
+        let (lower_tail, upper_tail) = self.1.split_at(index);
+        (
+            ComponentScannerUnion(lower_head, lower_tail),
+            ComponentScannerUnion(upper_head, upper_tail),
+        )
+    }
+}
+
+// One side of a union, which has to hold on to the next entry of its scanner until the other side
+// catches up to it.
+struct UnionSide<S: ComponentScanner> {
+    scanner: S,
+    next: Option<(S::Item, usize)>,
+    done: bool,
+}
+
+impl<S: ComponentScanner> UnionSide<S> {
+    fn new(scanner: S) -> UnionSide<S> {
+        UnionSide {
+            scanner,
+            next: None,
+            done: false,
+        }
+    }
+
+    fn peek(&mut self, until: Option<usize>) -> Option<usize> {
+        if !self.done {
+            match self.next {
+                Some((_, index)) if index >= until.unwrap_or(0) => {}
+                _ => {
+                    self.next = self.scanner.scan(until);
+                    self.done = self.next.is_none();
                 }
-            },
+            }
+        }
+        self.next.as_ref().map(|&(_, index)| index)
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        let pending = self.next.is_some() as usize;
+        self.scanner.estimated_len().map(|len| len + pending)
+    }
+
+    fn index_range(&self) -> Range<usize>
+    where
+        S: SplitScanner,
+    {
+        let range = self.scanner.index_range();
+        match self.next {
+            Some((_, index)) if range.start < range.end => index..range.end,
+            Some((_, index)) => index..index + 1,
+            None if self.done => 0..0,
+            None => range,
+        }
+    }
+
+    // The entry already taken from the scanner goes to the half that it is in, and the other half
+    // takes entries from its own scanner.
+    fn split_at(self, index: usize) -> (UnionSide<S>, UnionSide<S>)
+    where
+        S: SplitScanner,
+    {
+        let (lower, upper) = self.scanner.split_at(index);
+        let (lower_next, upper_next) = match self.next {
+            Some((item, next_index)) if next_index < index => (Some((item, next_index)), None),
+            next => (None, next),
+        };
+        let lower = UnionSide {
+            scanner: lower,
+            next: lower_next,
+            done: self.done,
+        };
+        let upper = UnionSide {
+            scanner: upper,
+            next: upper_next,
+            done: self.done,
+        };
+        (lower, upper)
+    }
+
+    fn take(&mut self, index: usize) -> Option<S::Item> {
+        match self.next {
+            Some((_, next_index)) if next_index == index => self.next.take().map(|(item, _)| item),
             _ => None,
         }
     }
@@ -253,8 +824,10 @@ impl<H: ComponentScanner, T: ComponentScanner> ComponentScanner for ComponentSca
 
 pub trait ComponentScannerTuple {
     type JoinScanner: ComponentScanner;
+    type UnionScanner: ComponentScanner;
 
     fn join(self) -> Self::JoinScanner;
+    fn union(self) -> Self::UnionScanner;
 }
 
 macro_rules! impl_tuple {
@@ -263,10 +836,16 @@ macro_rules! impl_tuple {
             where $first: ComponentScanner,
         {
             type JoinScanner = ComponentScannerMap<$first, fn($first::Item) -> ($first::Item,)>;
+            type UnionScanner =
+                ComponentScannerMap<$first, fn($first::Item) -> (Option<$first::Item>,)>;
 
             fn join(self) -> Self::JoinScanner {
                 self.0.map(|item| (item,))
             }
+
+            fn union(self) -> Self::UnionScanner {
+                self.0.map(|item| (Some(item),))
+            }
         }
     );
 
@@ -282,33 +861,70 @@ macro_rules! impl_tuple {
                 )) -> ($first::Item, $($rest::Item,)*)
             >;
 
+            type UnionScanner = ComponentScannerMap<
+                ComponentScannerUnion<$first, <($($rest,)*) as ComponentScannerTuple>::UnionScanner>,
+                fn((Option<$first::Item>,
+                    Option<<<($($rest,)*) as ComponentScannerTuple>::UnionScanner as ComponentScanner>::Item>
+                )) -> (Option<$first::Item>, $(Option<$rest::Item>,)*)
+            >;
+
             #[allow(non_snake_case)]
             fn join(self) -> Self::JoinScanner {
                 let ($first, $($rest,)*) = self;
-                ComponentScannerJoin($first, ($($rest,)*).join())
+                ComponentScannerJoin::new($first, ($($rest,)*).join())
                     .map(|($first, ($($rest,)*))| ($first, $($rest,)*))
             }
+
+            #[allow(non_snake_case)]
+            fn union(self) -> Self::UnionScanner {
+                let ($first, $($rest,)*) = self;
+                $first.or(($($rest,)*).union()).map(|($first, rest)| {
+                    let ($($rest,)*) = rest.unwrap_or(($(None::<$rest::Item>,)*));
+                    ($first, $($rest,)*)
+                })
+            }
         }
     );
 }
 
-pub fn component_scan_join<T: ComponentScannerTuple>(t: T) -> T::JoinScanner {
-    t.join()
-}
-
-impl_tuple!{A}
-impl_tuple!{A B}
-impl_tuple!{A B C}
-impl_tuple!{A B C D}
-impl_tuple!{A B C D E}
-impl_tuple!{A B C D E F}
-impl_tuple!{A B C D E F G}
-impl_tuple!{A B C D E F G H}
-impl_tuple!{A B C D E F G H I}
-impl_tuple!{A B C D E F G H I J}
-impl_tuple!{A B C D E F G H I J K}
-impl_tuple!{A B C D E F G H I J K L}
-impl_tuple!{A B C D E F G H I J K L M}
-impl_tuple!{A B C D E F G H I J K L M N}
-impl_tuple!{A B C D E F G H I J K L M N O}
-impl_tuple!{A B C D E F G H I J K L M N O P}
+/// Scans every index where all of the given scanners have a value.
+///
+/// Returns `Error::UnboundedJoin` if none of the scanners ends on its own, such as when they are
+/// all made with `opt_until(usize::MAX)`, since scanning the join past the last real entry would
+/// loop forever.
+pub fn component_scan_join<T: ComponentScannerTuple>(t: T) -> Result<T::JoinScanner, Error> {
+    bounded(t.join())
+}
+
+// Checks that a join ends on its own, since scanning it past the last real entry of its scanners
+// would loop forever.
+fn bounded<S: ComponentScanner>(scanner: S) -> Result<S, Error> {
+    if scanner.is_bounded() {
+        Ok(scanner)
+    } else {
+        Err(Error::UnboundedJoin)
+    }
+}
+
+/// Scans every index where any of the given scanners has a value, returning an Option for each
+/// scanner.
+pub fn component_scan_union<T: ComponentScannerTuple>(t: T) -> T::UnionScanner {
+    t.union()
+}
+
+impl_tuple! {A}
+impl_tuple! {A B}
+impl_tuple! {A B C}
+impl_tuple! {A B C D}
+impl_tuple! {A B C D E}
+impl_tuple! {A B C D E F}
+impl_tuple! {A B C D E F G}
+impl_tuple! {A B C D E F G H}
+impl_tuple! {A B C D E F G H I}
+impl_tuple! {A B C D E F G H I J}
+impl_tuple! {A B C D E F G H I J K}
+impl_tuple! {A B C D E F G H I J K L}
+impl_tuple! {A B C D E F G H I J K L M}
+impl_tuple! {A B C D E F G H I J K L M N}
+impl_tuple! {A B C D E F G H I J K L M N O}
+impl_tuple! {A B C D E F G H I J K L M N O P}
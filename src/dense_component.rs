@@ -1,8 +1,11 @@
 use std::cmp;
+use std::ops::Range;
 use std::slice;
 
+use change_tick::{ComponentTicks, TickedStorage};
 use component::ComponentStorage;
 use component_scanner::ComponentScanner;
+use parallel_scan::SplitScanner;
 
 #[derive(Clone)]
 pub struct DenseComponentStorage<T>(Vec<Option<T>>);
@@ -23,6 +26,10 @@ impl<T> Default for DenseComponentStorage<T> {
     }
 }
 
+impl<T> TickedStorage for DenseComponentStorage<T> {
+    type Ticks = DenseComponentStorage<ComponentTicks>;
+}
+
 impl<'a, T: 'static + Send + Sync + Clone> ComponentStorage<'a> for DenseComponentStorage<T> {
     type Component = T;
     type Scan = DenseComponentScanner<'a, T>;
@@ -75,6 +82,27 @@ impl<'a, T: 'static + Send + Sync + Clone> ComponentStorage<'a> for DenseCompone
             iter: self.0.iter_mut(),
         }
     }
+
+    fn scan_range(&'a self, range: Range<usize>) -> Self::Scan {
+        let end = cmp::min(range.end, self.0.len());
+        DenseComponentScanner {
+            next_index: range.start,
+            slice: &self.0[..end],
+        }
+    }
+
+    fn scan_mut_range(&'a mut self, range: Range<usize>) -> Self::ScanMut {
+        let end = cmp::min(range.end, self.0.len());
+        let start = cmp::min(range.start, end);
+        DenseComponentScannerMut {
+            next_index: start,
+            iter: self.0[start..end].iter_mut(),
+        }
+    }
+
+    fn estimated_len(&self) -> usize {
+        self.0.len()
+    }
 }
 
 impl<T: 'static> DenseComponentStorage<T> {
@@ -102,6 +130,10 @@ impl<'a, T> ComponentScanner for DenseComponentScanner<'a, T> {
             i += 1;
         }
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.slice.len().saturating_sub(self.next_index))
+    }
 }
 
 impl<'a, T> ComponentScanner for DenseComponentScannerMut<'a, T> {
@@ -134,4 +166,47 @@ impl<'a, T> ComponentScanner for DenseComponentScannerMut<'a, T> {
             }
         }
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+impl<'a, T> SplitScanner for DenseComponentScanner<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        self.next_index..self.slice.len()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let lower = DenseComponentScanner {
+            next_index: self.next_index,
+            slice: &self.slice[..cmp::min(index, self.slice.len())],
+        };
+        let upper = DenseComponentScanner {
+            next_index: cmp::max(self.next_index, index),
+            slice: self.slice,
+        };
+        (lower, upper)
+    }
+}
+
+impl<'a, T> SplitScanner for DenseComponentScannerMut<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        self.next_index..self.next_index + self.iter.len()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let slice = self.iter.into_slice();
+        let mid = cmp::min(index.saturating_sub(self.next_index), slice.len());
+        let (lower, upper) = slice.split_at_mut(mid);
+        let lower = DenseComponentScannerMut {
+            next_index: self.next_index,
+            iter: lower.iter_mut(),
+        };
+        let upper = DenseComponentScannerMut {
+            next_index: self.next_index + mid,
+            iter: upper.iter_mut(),
+        };
+        (lower, upper)
+    }
 }
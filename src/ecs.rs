@@ -1,73 +1,204 @@
-use std::any::TypeId;
+use std::any::{type_name, Any, TypeId};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::ops::{Deref, DerefMut};
-use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::ops::{Deref, DerefMut, Range};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use anymap::AnyMap;
 use downcast_rs::Downcast;
 
+use bundle::Bundle;
+use change_tick::{ChangeTicks, TickSinceScanner, TicksOf, TrackedMut, TrackedScanMut};
 use component::{Component, ComponentStorage};
+use component_hooks::ComponentHooks;
+use component_scanner::{ComponentScanner, ComponentScannerOpt};
 use entity::{Entity, EntityAllocator, EntityScanner, EntitySet, EntitySetScanner};
+use error::Error;
+use lock_tracker::HeldLock;
+use lock_wait::LockWait;
+use removal_log::RemovalLog;
+use shared_read_guard::{SharedReadGuard, SharedReadLock};
+use world_multi_lock::LockId;
 
 pub struct Ecs {
     entities: EntityAllocator,
-    components: HashMap<TypeId, Box<GenericComponentEntry>>,
+    components: HashMap<TypeId, Box<dyn GenericComponentEntry>>,
+    change_tick: AtomicU64,
 }
 
-#[derive(Debug, Fail)]
-#[fail(display = "ECS component type is unregistered")]
-pub struct UnregisteredComponent;
+impl Default for Ecs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Ecs {
     pub fn new() -> Ecs {
         Ecs {
             entities: EntityAllocator::new(),
             components: HashMap::new(),
+            change_tick: AtomicU64::new(1),
         }
     }
 
+    /// Registers the component type if it is not already registered, and sets its hooks.
+    pub fn register_component_with_hooks<T: Component>(&mut self, hooks: ComponentHooks<T>) {
+        self.register_component::<T>();
+        self.components
+            .get_mut(&TypeId::of::<T>())
+            .unwrap()
+            .downcast_mut::<ComponentEntry<T>>()
+            .expect("improper ComponentEntry type")
+            .get_mut_ignore_poison()
+            .hooks = hooks;
+    }
+
     pub fn register_component<T: 'static + Component>(&mut self) {
         let type_id = TypeId::of::<T>();
-        if !self.components.contains_key(&type_id) {
-            self.components
-                .insert(type_id, Box::new(ComponentEntry::<T::Storage>::new()));
+        self.components
+            .entry(type_id)
+            .or_insert_with(|| Box::new(ComponentEntry::<T>::new()));
+    }
+
+    /// The current world change tick, which every component added or changed is marked with.
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    /// Advances the world change tick and returns the previous one.  Every component added or
+    /// changed after this call will be found by scanning for changes since the returned tick.
+    pub fn increment_change_tick(&self) -> u64 {
+        self.change_tick.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn is_registered<T: Component>(&self) -> bool {
+        self.components.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Starts logging every removal of the given component type, optionally along with the removed
+    /// components, see `RemovalLog`.  Does nothing if removals are already tracked.
+    pub fn track_removals<T: Component>(&mut self, keep_values: bool) -> Result<(), Error> {
+        let data = self
+            .components
+            .get_mut(&TypeId::of::<T>())
+            .ok_or_else(Error::unregistered_component::<T>)?
+            .downcast_mut::<ComponentEntry<T>>()
+            .expect("improper ComponentEntry type")
+            .get_mut_ignore_poison();
+        data.removals
+            .get_or_insert_with(|| RemovalLog::new(keep_values));
+        Ok(())
+    }
+
+    /// Trims the removal log of every tracked component type, see `RemovalLog::trim`.
+    pub fn trim_removals(&mut self) {
+        for (_, cm) in self.components.iter_mut() {
+            cm.trim_removals();
         }
     }
 
-    pub fn add_entity(
+    pub fn add_entity(&mut self, components: Option<AnyMap>) -> Result<Entity, Error> {
+        self.add_named_entity(components, &[])
+    }
+
+    /// Like `add_entity`, but unregistered component types are reported by the given names rather
+    /// than by their `TypeId`.
+    pub(crate) fn add_named_entity(
         &mut self,
         components: Option<AnyMap>,
-    ) -> Result<Entity, UnregisteredComponent> {
+        type_names: &[(TypeId, &'static str)],
+    ) -> Result<Entity, Error> {
+        if let Some(components) = &components {
+            self.check_registered(components, type_names)?;
+        }
         let entity = self.entities.allocate();
         match components {
-            Some(components) => self.insert_components(entity, components).map(|_| entity),
+            Some(components) => self
+                .insert_named_components(entity, components, type_names)
+                .map(|_| entity),
             None => Ok(entity),
         }
     }
 
+    /// Reserves an entity without needing exclusive access, the entity is not live and cannot have
+    /// components until `allocate_reserved_entities` is called, which happens automatically on the
+    /// next call to `add_entity` or `remove_entity`.
+    pub fn reserve_entity(&self) -> Entity {
+        self.entities.reserve()
+    }
+
+    pub fn allocate_reserved_entities(&mut self) {
+        self.entities.allocate_reserved()
+    }
+
     /// If the entity is dead, does nothing and returns None, otherwise returns the set of
-    /// overwritten components.
+    /// overwritten components.  If any of the components are unregistered, returns an error
+    /// without inserting any of them.
     pub fn insert_components(
+        &mut self,
+        entity: Entity,
+        components: AnyMap,
+    ) -> Result<Option<AnyMap>, Error> {
+        self.insert_named_components(entity, components, &[])
+    }
+
+    /// Like `insert_components`, but unregistered component types are reported by the given names
+    /// rather than by their `TypeId`.
+    pub(crate) fn insert_named_components(
         &mut self,
         entity: Entity,
         mut components: AnyMap,
-    ) -> Result<Option<AnyMap>, UnregisteredComponent> {
+        type_names: &[(TypeId, &'static str)],
+    ) -> Result<Option<AnyMap>, Error> {
+        self.check_registered(&components, type_names)?;
         if !self.entities.is_live(entity) {
             return Ok(None);
         }
 
+        let tick = self.change_tick();
         let mut overwritten = AnyMap::new();
         for (_, cm) in self.components.iter_mut() {
-            cm.insert_entity_from(entity.index(), &mut components, &mut overwritten);
+            cm.insert_entity_from(entity, &mut components, &mut overwritten, tick);
+        }
+        debug_assert!(components.is_empty());
+
+        Ok(Some(overwritten))
+    }
+
+    /// Adds a new entity with the components in the given bundle.  If any of the component types
+    /// are unregistered or poisoned, returns an error without adding the entity.
+    pub fn add_bundle<B: Bundle>(&mut self, bundle: B) -> Result<Entity, Error> {
+        let entity = self.entities.allocate();
+        if let Err(err) = bundle.insert(self, entity) {
+            // Nothing was inserted, so the entity can be freed as if it never existed.
+            self.entities.deallocate(entity);
+            return Err(err);
         }
+        Ok(entity)
+    }
 
-        // If you could get TypeIds out of anymap::raw::RawMap, you could pre-check this rather than
-        // post-check it.
-        if !components.is_empty() {
-            return Err(UnregisteredComponent);
+    /// Inserts the components in the given bundle into a live entity, overwriting any existing
+    /// components of the same types, and returns the overwritten components.  Returns None and does
+    /// nothing if the entity is dead.  If any of the component types are unregistered or poisoned,
+    /// returns an error without inserting any of them.
+    pub fn insert_bundle<B: Bundle>(
+        &mut self,
+        entity: Entity,
+        bundle: B,
+    ) -> Result<Option<B::Replaced>, Error> {
+        match bundle.insert(self, entity) {
+            Ok(replaced) => Ok(Some(replaced)),
+            Err(Error::EntityDead(_)) => Ok(None),
+            Err(err) => Err(err),
         }
+    }
 
-        Ok(Some(overwritten))
+    /// Removes and returns the components in a bundle from an entity, only if the entity is live
+    /// and has every one of them.  If any of the component types are poisoned, returns an error
+    /// without removing any of them.
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: Entity) -> Result<Option<B>, Error> {
+        B::remove(self, entity)
     }
 
     /// Does nothing and returns None if the entity is already dead.
@@ -75,7 +206,7 @@ impl Ecs {
         if self.entities.deallocate(entity) {
             let mut components = AnyMap::new();
             for (_, cm) in self.components.iter_mut() {
-                cm.remove_entity_into(entity.index(), &mut components);
+                cm.remove_entity_into(entity, &mut components);
             }
             Some(components)
         } else {
@@ -104,7 +235,7 @@ impl Ecs {
 
     /// Scans through all live entities, join this with other component scans to get the Entity
     /// associated with a set of components.
-    pub fn scan_entities(&self) -> EntityScanner {
+    pub fn scan_entities(&self) -> EntityScanner<'_> {
         self.entities.scan_live()
     }
 
@@ -118,61 +249,165 @@ impl Ecs {
         self.entities.prune_set(set)
     }
 
+    fn check_registered(
+        &self,
+        components: &AnyMap,
+        names: &[(TypeId, &'static str)],
+    ) -> Result<(), Error> {
+        let type_names = components
+            .as_ref()
+            .iter()
+            .map(|c| {
+                let c: &dyn Any = c;
+                c.type_id()
+            })
+            .filter(|type_id| !self.components.contains_key(type_id))
+            .map(
+                |type_id| match names.iter().find(|&&(id, _)| id == type_id) {
+                    Some(&(_, name)) => Cow::Borrowed(name),
+                    None => Cow::Owned(format!("{:?}", type_id)),
+                },
+            )
+            .collect::<Vec<_>>();
+
+        if type_names.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::UnregisteredComponent { type_names })
+        }
+    }
+
+    /// Returns true if the component type is registered and its storage is poisoned, see
+    /// `clear_component_poison`.
+    pub fn is_component_poisoned<T: Component>(&self) -> bool {
+        self.components.get(&TypeId::of::<T>()).is_some_and(|c| {
+            c.downcast_ref::<ComponentEntry<T>>()
+                .expect("improper ComponentEntry type")
+                .0
+                .is_poisoned()
+        })
+    }
+
+    /// Clears the poisoned state of a component storage, left by a panic while it was locked for
+    /// writing, so that it can be accessed again.  The storage is left as the panic left it.
+    pub fn clear_component_poison<T: Component>(&self) -> Result<(), Error> {
+        self.components
+            .get(&TypeId::of::<T>())
+            .ok_or_else(Error::unregistered_component::<T>)?
+            .downcast_ref::<ComponentEntry<T>>()
+            .expect("improper ComponentEntry type")
+            .0
+            .clear_poison();
+        Ok(())
+    }
+
     /// Get a read only handle to a component storage by acquiring a read lock on that component
-    /// storage.
-    pub fn read_component<T>(&self) -> Result<ComponentReadHandle<T>, UnregisteredComponent>
+    /// storage.  Returns an error if the storage is poisoned, see `clear_component_poison`.
+    pub fn read_component<T>(&self) -> Result<ComponentReadHandle<'_, T>, Error>
+    where
+        T: 'static + Component,
+    {
+        self.read_component_with(LockWait::Block)
+    }
+
+    /// Like `read_component`, but returns `Error::WouldBlock` if the lock is not acquired within
+    /// the given wait.
+    pub fn read_component_with<T>(
+        &self,
+        wait: LockWait,
+    ) -> Result<ComponentReadHandle<'_, T>, Error>
     where
         T: 'static + Component,
     {
         let r = self
             .components
             .get(&TypeId::of::<T>())
-            .ok_or(UnregisteredComponent)?
-            .downcast_ref::<ComponentEntry<T::Storage>>()
+            .ok_or_else(Error::unregistered_component::<T>)?
+            .downcast_ref::<ComponentEntry<T>>()
             .expect("improper ComponentEntry type")
-            .read();
-        Ok(ComponentHandle(r, &self.entities))
+            .read(wait)?;
+        Ok(ComponentHandle {
+            data: SharedReadGuard::new(r.0, r.1),
+            entities: &self.entities,
+            change_tick: &self.change_tick,
+            _held: None,
+        })
+    }
+
+    /// Returns a handle that shares the given read lock on the storage for `T`, which must come
+    /// from another `ComponentReadHandle<T>`.
+    pub(crate) fn share_component<'a, T: Component>(
+        &'a self,
+        lock: SharedReadLock<'a>,
+    ) -> ComponentReadHandle<'a, T> {
+        ComponentHandle {
+            data: SharedReadGuard::from_shared(lock),
+            entities: &self.entities,
+            change_tick: &self.change_tick,
+            _held: None,
+        }
     }
 
     /// Get a read/write handle to a component storage by acquiring a write lock on that component
-    /// storage.
-    pub fn write_component<T>(&self) -> Result<ComponentWriteHandle<T>, UnregisteredComponent>
+    /// storage.  Returns an error if the storage is poisoned, see `clear_component_poison`.
+    pub fn write_component<T>(&self) -> Result<ComponentWriteHandle<'_, T>, Error>
+    where
+        T: 'static + Component,
+    {
+        self.write_component_with(LockWait::Block)
+    }
+
+    /// Like `write_component`, but returns `Error::WouldBlock` if the lock is not acquired within
+    /// the given wait.
+    pub fn write_component_with<T>(
+        &self,
+        wait: LockWait,
+    ) -> Result<ComponentWriteHandle<'_, T>, Error>
     where
         T: 'static + Component,
     {
         let w = self
             .components
             .get(&TypeId::of::<T>())
-            .ok_or(UnregisteredComponent)?
-            .downcast_ref::<ComponentEntry<T::Storage>>()
+            .ok_or_else(Error::unregistered_component::<T>)?
+            .downcast_ref::<ComponentEntry<T>>()
             .expect("improper ComponentEntry type")
-            .write();
-        Ok(ComponentHandle(w, &self.entities))
+            .write(wait)?;
+        Ok(ComponentHandle {
+            data: w.0,
+            entities: &self.entities,
+            change_tick: &self.change_tick,
+            _held: Some(w.1),
+        })
     }
 
     /// Get a read/write handle to a component storage by mutable borrow, no locking needs to take
     /// place, but this will borrow Ecs mutably, and thus only one component storage may be obtained
     /// at a time.
-    pub fn get_mut_component<T>(
-        &mut self,
-    ) -> Result<ComponentGetMutHandle<T>, UnregisteredComponent>
+    pub fn get_mut_component<T>(&mut self) -> Result<ComponentGetMutHandle<'_, T>, Error>
     where
         T: Component,
     {
         let w = self
             .components
             .get_mut(&TypeId::of::<T>())
-            .ok_or(UnregisteredComponent)?
-            .downcast_mut::<ComponentEntry<T::Storage>>()
+            .ok_or_else(Error::unregistered_component::<T>)?
+            .downcast_mut::<ComponentEntry<T>>()
             .expect("improper ComponentEntry type")
-            .get_mut();
-        Ok(ComponentHandle(w, &self.entities))
+            .get_mut()?;
+        Ok(ComponentHandle {
+            data: w,
+            entities: &self.entities,
+            change_tick: &self.change_tick,
+            _held: None,
+        })
     }
 }
 
 impl Clone for Ecs {
     /// For consistency, cloning an Ecs will lock all of the component storages for reading at once,
-    /// then clone them, then unlock them.
+    /// then clone them, then unlock them.  Poisoned storages are cloned as they are, and the clones
+    /// are not poisoned.
     fn clone(&self) -> Ecs {
         let mut clone_locks = Vec::new();
         for (type_id, component) in self.components.iter() {
@@ -186,26 +421,132 @@ impl Clone for Ecs {
 
         Ecs {
             entities: self.entities.clone(),
-            components: components,
+            components,
+            change_tick: AtomicU64::new(self.change_tick()),
         }
     }
 }
 
-pub struct ComponentHandle<'a, R: 'a>(R, &'a EntityAllocator);
+/// A component storage along with the change ticks of its components, its optional removal log and
+/// its hooks, all behind the same lock.
+pub struct ComponentData<T: Component> {
+    storage: T::Storage,
+    ticks: ChangeTicks<TicksOf<T>>,
+    removals: Option<RemovalLog<T>>,
+    hooks: ComponentHooks<T>,
+}
+
+impl<T: Component> Default for ComponentData<T> {
+    fn default() -> ComponentData<T> {
+        ComponentData {
+            storage: T::Storage::default(),
+            ticks: ChangeTicks::new(),
+            removals: None,
+            hooks: ComponentHooks::new(),
+        }
+    }
+}
+
+impl<T: Component> Clone for ComponentData<T> {
+    fn clone(&self) -> ComponentData<T> {
+        ComponentData {
+            storage: self.storage.clone(),
+            ticks: self.ticks.clone(),
+            removals: self.removals.clone(),
+            hooks: self.hooks.clone(),
+        }
+    }
+}
 
-pub type ComponentReadHandle<'a, T> =
-    ComponentHandle<'a, RwLockReadGuard<'a, <T as Component>::Storage>>;
-pub type ComponentWriteHandle<'a, T> =
-    ComponentHandle<'a, RwLockWriteGuard<'a, <T as Component>::Storage>>;
-pub type ComponentGetMutHandle<'a, T> = ComponentHandle<'a, &'a mut <T as Component>::Storage>;
+pub struct ComponentHandle<'a, R: 'a> {
+    data: R,
+    entities: &'a EntityAllocator,
+    change_tick: &'a AtomicU64,
+    _held: Option<HeldLock>,
+}
+
+pub type ComponentReadHandle<'a, T> = ComponentHandle<'a, SharedReadGuard<'a, ComponentData<T>>>;
+pub type ComponentWriteHandle<'a, T> = ComponentHandle<'a, RwLockWriteGuard<'a, ComponentData<T>>>;
+pub type ComponentGetMutHandle<'a, T> = ComponentHandle<'a, &'a mut ComponentData<T>>;
+
+impl<'a, 'b, T: Component, R: 'a + Deref<Target = ComponentData<T>>> ComponentHandle<'a, R> {
+    /// Returns the component for the given entity if it has one, or an error if the entity is dead.
+    pub fn get(&'b self, entity: Entity) -> Result<Option<&'b T>, Error> {
+        self.check_live(entity)?;
+        Ok(self.data.storage.get(entity.index()))
+    }
+
+    pub fn contains(&'b self, entity: Entity) -> Result<bool, Error> {
+        self.get(entity).map(|c| c.is_some())
+    }
+
+    pub fn scan(&'b self) -> <T::Storage as ComponentStorage<'b>>::Scan {
+        self.data.storage.scan()
+    }
+
+    pub fn scan_range(&'b self, range: Range<usize>) -> <T::Storage as ComponentStorage<'b>>::Scan {
+        self.data.storage.scan_range(range)
+    }
+
+    /// Scans every index that could hold a live entity, returning None where there is no component.
+    pub fn scan_opt(
+        &'b self,
+    ) -> ComponentScannerOpt<<T::Storage as ComponentStorage<'b>>::Scan, &'b T> {
+        self.scan().opt_until(self.entities.max_allocated_index())
+    }
+
+    /// Scans only the components that were added after the given world change tick.
+    pub fn scan_added_since(
+        &'b self,
+        tick: u64,
+    ) -> TickSinceScanner<'b, <T::Storage as ComponentStorage<'b>>::Scan, TicksOf<T>> {
+        self.data
+            .ticks
+            .scan_added_since(self.data.storage.scan(), tick)
+    }
+
+    /// Scans only the components that were added or changed after the given world change tick.
+    pub fn scan_changed_since(
+        &'b self,
+        tick: u64,
+    ) -> TickSinceScanner<'b, <T::Storage as ComponentStorage<'b>>::Scan, TicksOf<T>> {
+        self.data
+            .ticks
+            .scan_changed_since(self.data.storage.scan(), tick)
+    }
+
+    pub fn change_ticks(&self) -> &ChangeTicks<TicksOf<T>> {
+        &self.data.ticks
+    }
+
+    /// The log of removed components, if removals of this component type are tracked.
+    pub fn removals(&self) -> Option<&RemovalLog<T>> {
+        self.data.removals.as_ref()
+    }
+}
+
+impl<'a, T: Component> ComponentReadHandle<'a, T> {
+    /// Returns the handle along with its lock, for other handles of the same storage to share.
+    pub(crate) fn into_shared(self) -> (ComponentReadHandle<'a, T>, SharedReadLock<'a>) {
+        let (data, lock) = self.data.into_shared();
+        (ComponentHandle { data, ..self }, lock)
+    }
+}
 
-impl<'a, 'b, S: ComponentStorage<'b>, R: 'a + Deref<Target = S>> ComponentHandle<'a, R> {
-    pub fn get(&'b self, entity: Entity) -> Option<&'b S::Component> {
-        self.0.get(entity.index())
+impl<'a, R: 'a> ComponentHandle<'a, R> {
+    /// Components are stored by entity index only, so every access through an `Entity` must check
+    /// that it is live, otherwise a stale `Entity` would see the components of whatever entity
+    /// currently occupies its index.
+    fn check_live(&self, entity: Entity) -> Result<(), Error> {
+        if self.entities.is_live(entity) {
+            Ok(())
+        } else {
+            Err(Error::EntityDead(entity))
+        }
     }
 
-    pub fn scan(&'b self) -> S::Scan {
-        self.0.scan()
+    fn current_tick(&self) -> u64 {
+        self.change_tick.load(Ordering::Relaxed)
     }
 }
 
@@ -215,103 +556,191 @@ pub enum ComponentInsertResult<T> {
     EntityIsDead(T),
 }
 
-impl<'a, 'b, S: ComponentStorage<'b>, R: 'a + DerefMut<Target = S>> ComponentHandle<'a, R> {
-    pub fn get_mut(&'b mut self, entity: Entity) -> Option<&'b mut S::Component> {
-        self.0.get_mut(entity.index())
+impl<'a, 'b, T: Component, R: 'a + DerefMut<Target = ComponentData<T>>> ComponentHandle<'a, R> {
+    /// Marks the returned component as changed.
+    pub fn get_mut(&'b mut self, entity: Entity) -> Result<Option<&'b mut T>, Error> {
+        self.check_live(entity)?;
+        let tick = self.current_tick();
+        let data = &mut *self.data;
+        let component = data.storage.get_mut(entity.index());
+        if component.is_some() {
+            data.ticks.set_changed(entity.index(), tick);
+        }
+        Ok(component)
     }
 
-    pub fn insert(
-        &'b mut self,
-        entity: Entity,
-        component: S::Component,
-    ) -> ComponentInsertResult<S::Component> {
-        if !self.1.is_live(entity) {
+    pub fn insert(&'b mut self, entity: Entity, component: T) -> ComponentInsertResult<T> {
+        if !self.entities.is_live(entity) {
             ComponentInsertResult::EntityIsDead(component)
         } else {
-            if let Some(old) = self.0.insert(entity.index(), component) {
-                ComponentInsertResult::Updated(old)
-            } else {
-                ComponentInsertResult::Inserted
+            let tick = self.current_tick();
+            match self.data.insert(entity, component, tick) {
+                Some(old) => ComponentInsertResult::Updated(old),
+                None => ComponentInsertResult::Inserted,
             }
         }
     }
 
-    pub fn remove(&'b mut self, entity: Entity) -> Option<S::Component> {
-        if self.1.is_live(entity) {
-            self.0.remove(entity.index())
-        } else {
-            None
+    /// Removes and returns the component for the given entity if it has one, or returns an error
+    /// if the entity is dead.
+    pub fn remove(&'b mut self, entity: Entity) -> Result<Option<T>, Error> {
+        self.check_live(entity)?;
+        Ok(self.data.remove(entity))
+    }
+
+    /// Marks each scanned component as changed once it is mutably dereferenced, see `TrackedMut`.
+    pub fn scan_mut(&'b mut self) -> TrackedScanMut<'b, T> {
+        let tick = self.current_tick();
+        let data = &mut *self.data;
+        data.ticks.track_changes(data.storage.scan_mut(), tick)
+    }
+
+    /// Marks each scanned component as changed once it is mutably dereferenced, see `TrackedMut`.
+    pub fn scan_mut_range(&'b mut self, range: Range<usize>) -> TrackedScanMut<'b, T> {
+        let tick = self.current_tick();
+        let data = &mut *self.data;
+        data.ticks
+            .track_changes(data.storage.scan_mut_range(range), tick)
+    }
+
+    /// Scans every index that could hold a live entity, returning None where there is no component.
+    /// Marks each scanned component as changed once it is mutably dereferenced, see `TrackedMut`.
+    pub fn scan_mut_opt(
+        &'b mut self,
+    ) -> ComponentScannerOpt<TrackedScanMut<'b, T>, TrackedMut<'b, T>> {
+        let end = self.entities.max_allocated_index();
+        self.scan_mut().opt_until(end)
+    }
+}
+
+impl<T: Component> ComponentData<T> {
+    // Every insert goes through here so that ticks are updated and hooks are called.
+    fn insert(&mut self, entity: Entity, component: T, tick: u64) -> Option<T> {
+        let index = entity.index();
+        let old = self.storage.insert(index, component);
+        let new = self.storage.get(index).expect("inserted component missing");
+        match &old {
+            Some(old) => {
+                self.ticks.set_changed(index, tick);
+                self.hooks.replaced(entity, old, new);
+            }
+            None => {
+                self.ticks.set_added(index, tick);
+                self.hooks.inserted(entity, new);
+            }
         }
+        old
     }
 
-    pub fn scan_mut(&'b mut self) -> S::ScanMut {
-        self.0.scan_mut()
+    // Every removal goes through here so that ticks are cleared, the removal is logged and hooks
+    // are called.
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        self.ticks.clear(entity.index());
+        let component = self.storage.remove(entity.index())?;
+        if let Some(removals) = &mut self.removals {
+            removals.push(entity, || component.clone());
+        }
+        self.hooks.removed(entity, &component);
+        Some(component)
     }
 }
 
-struct ComponentEntry<S>(RwLock<S>);
+// Handles to a poisoned storage are refused, but structural changes to the whole `Ecs`, like
+// removing an entity, ignore poisoning so that one poisoned storage does not stop them.
+struct ComponentEntry<T: Component>(RwLock<ComponentData<T>>);
+
+impl<T: Component> ComponentEntry<T> {
+    fn new() -> ComponentEntry<T> {
+        ComponentEntry(RwLock::new(ComponentData::default()))
+    }
+
+    fn read(
+        &self,
+        wait: LockWait,
+    ) -> Result<(RwLockReadGuard<'_, ComponentData<T>>, HeldLock), Error> {
+        let held = Self::hold(wait);
+        let guard = wait.acquire::<T, _, _, _>(|| self.0.read(), || self.0.try_read())?;
+        Ok((guard, held))
+    }
+
+    fn write(
+        &self,
+        wait: LockWait,
+    ) -> Result<(RwLockWriteGuard<'_, ComponentData<T>>, HeldLock), Error> {
+        let held = Self::hold(wait);
+        let guard = wait.acquire::<T, _, _, _>(|| self.0.write(), || self.0.try_write())?;
+        Ok((guard, held))
+    }
 
-impl<'a, S: 'static + ComponentStorage<'a>> ComponentEntry<S> {
-    fn new() -> ComponentEntry<S> {
-        ComponentEntry(RwLock::new(S::default()))
+    fn hold(wait: LockWait) -> HeldLock {
+        HeldLock::acquire(LockId::Component(TypeId::of::<T>()), type_name::<T>(), wait)
     }
 
-    fn read(&self) -> RwLockReadGuard<S> {
-        self.0.read().unwrap()
+    fn get_mut(&mut self) -> Result<&mut ComponentData<T>, Error> {
+        self.0.get_mut().map_err(|_| Error::lock_poisoned::<T>())
     }
 
-    fn write(&self) -> RwLockWriteGuard<S> {
-        self.0.write().unwrap()
+    fn read_ignore_poison(&self) -> RwLockReadGuard<'_, ComponentData<T>> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
     }
 
-    fn get_mut(&mut self) -> &mut S {
-        self.0.get_mut().unwrap()
+    fn get_mut_ignore_poison(&mut self) -> &mut ComponentData<T> {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
     }
 }
 
 trait GenericComponentEntry: Send + Sync + Downcast {
     fn insert_entity_from(
         &mut self,
-        entity_index: usize,
+        entity: Entity,
         input: &mut AnyMap,
         overwritten: &mut AnyMap,
+        tick: u64,
     );
-    fn remove_entity_into(&mut self, entity_index: usize, output: &mut AnyMap);
+    fn remove_entity_into(&mut self, entity: Entity, output: &mut AnyMap);
+    fn trim_removals(&mut self);
     fn clone_entity_into(&self, entity_index: usize, output: &mut AnyMap);
 
-    fn clone_lock<'a>(&'a self) -> Box<Fn() -> Box<GenericComponentEntry> + 'a>;
+    fn clone_lock<'a>(&'a self) -> Box<dyn Fn() -> Box<dyn GenericComponentEntry> + 'a>;
 }
 impl_downcast!(GenericComponentEntry);
 
-impl<'a, S: 'static + ComponentStorage<'a>> GenericComponentEntry for ComponentEntry<S> {
+impl<T: Component> GenericComponentEntry for ComponentEntry<T> {
     fn insert_entity_from(
         &mut self,
-        entity_index: usize,
+        entity: Entity,
         input: &mut AnyMap,
         overwritten: &mut AnyMap,
+        tick: u64,
     ) {
-        if let Some(c) = input.remove::<S::Component>() {
-            if let Some(o) = self.0.get_mut().unwrap().insert(entity_index, c) {
+        if let Some(c) = input.remove::<T>() {
+            if let Some(o) = self.get_mut_ignore_poison().insert(entity, c, tick) {
                 overwritten.insert(o);
             }
         }
     }
 
-    fn remove_entity_into(&mut self, entity_index: usize, output: &mut AnyMap) {
-        if let Some(c) = self.0.get_mut().unwrap().remove(entity_index) {
+    fn remove_entity_into(&mut self, entity: Entity, output: &mut AnyMap) {
+        if let Some(c) = self.get_mut_ignore_poison().remove(entity) {
             output.insert(c);
         }
     }
 
+    fn trim_removals(&mut self) {
+        if let Some(removals) = &mut self.get_mut_ignore_poison().removals {
+            removals.trim();
+        }
+    }
+
     fn clone_entity_into(&self, entity_index: usize, output: &mut AnyMap) {
-        let storage = self.0.read().unwrap();
-        if let Some(c) = storage.get(entity_index) {
+        let data = self.read_ignore_poison();
+        if let Some(c) = data.storage.get(entity_index) {
             output.insert(c.clone());
         }
     }
 
-    fn clone_lock<'b>(&'b self) -> Box<Fn() -> Box<GenericComponentEntry> + 'b> {
-        let reader = self.0.read().unwrap();
-        Box::new(move || Box::new(ComponentEntry::<S>(RwLock::new(reader.clone()))))
+    fn clone_lock<'b>(&'b self) -> Box<dyn Fn() -> Box<dyn GenericComponentEntry> + 'b> {
+        let reader = self.read_ignore_poison();
+        Box::new(move || Box::new(ComponentEntry::<T>(RwLock::new(reader.clone()))))
     }
 }
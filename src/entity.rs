@@ -1,11 +1,14 @@
+use std::cmp;
 use std::collections::{btree_set, BTreeSet};
 use std::iter::FromIterator;
+use std::ops::Range;
 
 use component_scanner::ComponentScanner;
 use generational_index::{
     GenerationalIndex, GenerationalIndexAllocator, GenerationalIndexArray,
     GenerationalIndexArrayIntoIter, GenerationalIndexArrayIter, GenerationalIndexArrayIterMut,
 };
+use parallel_scan::SplitScanner;
 
 /// Uniquely identifies an entity, No allocated Entity will be equal to any other allocated Entity,
 /// live or dead.
@@ -14,7 +17,8 @@ pub struct Entity(GenerationalIndex);
 
 #[derive(Clone)]
 pub struct EntityAllocator(GenerationalIndexAllocator);
-pub struct EntityScanner<'a>(usize, &'a GenerationalIndexAllocator);
+// The next index to scan, the allocator, and the index to scan up to.
+pub struct EntityScanner<'a>(usize, &'a GenerationalIndexAllocator, usize);
 
 pub type EntitySet = BTreeSet<Entity>;
 pub struct EntitySetScanner<'a>(btree_set::Iter<'a, Entity>, &'a EntityAllocator);
@@ -40,6 +44,12 @@ impl Entity {
     }
 }
 
+impl Default for EntityAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EntityAllocator {
     pub fn new() -> EntityAllocator {
         EntityAllocator(GenerationalIndexAllocator::new())
@@ -53,13 +63,29 @@ impl EntityAllocator {
         self.0.deallocate(entity.0)
     }
 
+    /// Reserves an Entity through a shared reference, which does not become live until
+    /// `allocate_reserved` is called.
+    pub fn reserve(&self) -> Entity {
+        Entity(self.0.reserve())
+    }
+
+    pub fn allocate_reserved(&mut self) {
+        self.0.allocate_reserved()
+    }
+
     #[inline]
     pub fn is_live(&self, entity: Entity) -> bool {
         self.0.is_live(entity.0)
     }
 
-    pub fn scan_live(&self) -> EntityScanner {
-        EntityScanner(0, &self.0)
+    /// Every live entity has an index below this.
+    #[inline]
+    pub fn max_allocated_index(&self) -> usize {
+        self.0.max_allocated_index()
+    }
+
+    pub fn scan_live(&self) -> EntityScanner<'_> {
+        EntityScanner(0, &self.0, self.0.max_allocated_index())
     }
 
     pub fn scan_set<'a>(&'a self, set: &'a EntitySet) -> EntitySetScanner<'a> {
@@ -84,34 +110,55 @@ impl<'a> ComponentScanner for EntityScanner<'a> {
     type Item = Entity;
 
     fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
-        if until.is_some() && until.unwrap() > self.0 {
-            self.0 = until.unwrap();
+        if let Some(until) = until {
+            if until > self.0 {
+                self.0 = until;
+            }
         }
 
-        while self.0 < self.1.max_allocated_index() {
-            if let Some(gen_index) = self.1.live_at_index(self.0) {
-                return Some((Entity(gen_index), self.0));
-            } else {
-                self.0 += 1;
+        while self.0 < self.2 {
+            let index = self.0;
+            self.0 += 1;
+            if let Some(gen_index) = self.1.live_at_index(index) {
+                return Some((Entity(gen_index), index));
             }
         }
         None
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.2.saturating_sub(self.0))
+    }
+}
+
+impl<'a> SplitScanner for EntityScanner<'a> {
+    fn index_range(&self) -> Range<usize> {
+        self.0..self.2
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        (
+            EntityScanner(self.0, self.1, cmp::min(index, self.2)),
+            EntityScanner(cmp::max(self.0, index), self.1, self.2),
+        )
+    }
 }
 
 impl<'a> ComponentScanner for EntitySetScanner<'a> {
     type Item = Entity;
 
     fn scan(&mut self, until: Option<usize>) -> Option<(Entity, usize)> {
-        while let Some(&entity) = self.0.next() {
-            if until.is_none() || entity.index() >= until.unwrap() {
-                if self.1.is_live(entity) {
-                    return Some((entity, entity.index()));
-                }
+        for &entity in self.0.by_ref() {
+            if (until.is_none() || entity.index() >= until.unwrap()) && self.1.is_live(entity) {
+                return Some((entity, entity.index()));
             }
         }
         None
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
 }
 
 impl<T> EntityIndex<T> {
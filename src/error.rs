@@ -0,0 +1,73 @@
+use std::any::type_name;
+use std::borrow::Cow;
+
+use failure;
+
+use entity::Entity;
+
+/// Errors returned by `World` and `Ecs` operations.  Operations that run arbitrary systems, like
+/// `Dispatcher::dispatch`, instead return a `failure::Error` which may wrap this.
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Names every component type that was used without being registered.  Types that only arrive
+    /// type-erased in an `AnyMap` have no name available, so they are named by their `TypeId`.
+    #[fail(display = "component types are unregistered: {:?}", type_names)]
+    UnregisteredComponent { type_names: Vec<Cow<'static, str>> },
+    #[fail(display = "no such resource {}", type_name)]
+    MissingResource { type_name: &'static str },
+    /// The lock on a component storage or resource was poisoned by a panic while it was held.
+    #[fail(display = "lock on {} is poisoned", type_name)]
+    LockPoisoned { type_name: &'static str },
+    /// Accessed components through an `Entity` that is dead, including a stale `Entity` whose
+    /// index has since been re-used by a newer entity.
+    #[fail(display = "entity {:?} is dead", _0)]
+    EntityDead(Entity),
+    /// A lock could not be acquired without blocking.
+    #[fail(display = "lock on {} would block", type_name)]
+    WouldBlock { type_name: &'static str },
+    /// A bundle contained the same component type more than once.
+    #[fail(display = "component type {} is in a bundle more than once", type_name)]
+    DuplicateComponent { type_name: &'static str },
+    /// The same lock was requested more than once in a single `World::multi_lock` or query, where
+    /// at least one of the requests is a write.
+    #[fail(display = "conflicting locks on {} requested together", type_name)]
+    ConflictingLocks { type_name: &'static str },
+    /// None of the scanners in a `component_scan_join` ends on its own, so the join would never
+    /// end.
+    #[fail(display = "joined scanners are all unbounded")]
+    UnboundedJoin,
+    /// A command pushed to a `CommandBuffer` failed with an error of its own.
+    #[fail(display = "command failed: {}", _0)]
+    Command(failure::Error),
+}
+
+impl Error {
+    pub fn unregistered_component<T: 'static>() -> Error {
+        Error::UnregisteredComponent {
+            type_names: vec![Cow::Borrowed(type_name::<T>())],
+        }
+    }
+
+    pub fn missing_resource<T: 'static>() -> Error {
+        Error::MissingResource {
+            type_name: type_name::<T>(),
+        }
+    }
+
+    pub fn lock_poisoned<T: 'static>() -> Error {
+        Error::LockPoisoned {
+            type_name: type_name::<T>(),
+        }
+    }
+
+    pub fn would_block<T: 'static>() -> Error {
+        Error::WouldBlock {
+            type_name: type_name::<T>(),
+        }
+    }
+
+    /// Wraps an error of a command's own, for a command pushed to a `CommandBuffer` to return.
+    pub fn command<E: Into<failure::Error>>(err: E) -> Error {
+        Error::Command(err.into())
+    }
+}
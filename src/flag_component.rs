@@ -0,0 +1,243 @@
+use std::cmp;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+use std::ptr::NonNull;
+
+use bit_set::HierarchicalBitSet;
+use change_tick::{ComponentTicks, TickedStorage};
+use component::ComponentStorage;
+use component_scanner::ComponentScanner;
+use parallel_scan::SplitScanner;
+use sparse_component::SparseComponentStorage;
+
+/// Storage for zero-sized tag components, which only records which entity indexes have the tag in
+/// a `HierarchicalBitSet`.  Scanning jumps directly to the next set bit, so using a tag to `limit`
+/// or `not` another scan is very cheap.
+///
+/// Since every value of a zero-sized type is the same, the stored tag is created with `Default`
+/// and shared between all entities.  Using a component type that is not zero-sized fails to
+/// compile.
+///
+/// ```compile_fail
+/// use simplecs::flag_component::FlagComponentStorage;
+///
+/// let tags = FlagComponentStorage::<u32>::new();
+/// ```
+#[derive(Clone)]
+pub struct FlagComponentStorage<T> {
+    set: HierarchicalBitSet,
+    flag: T,
+}
+
+pub struct FlagComponentScanner<'a, T: 'a> {
+    next_index: usize,
+    end: usize,
+    set: &'a HierarchicalBitSet,
+    flag: &'a T,
+}
+
+pub struct FlagComponentScannerMut<'a, T: 'a> {
+    next_index: usize,
+    end: usize,
+    set: &'a HierarchicalBitSet,
+    flag: PhantomData<&'a mut T>,
+}
+
+impl<T> FlagComponentStorage<T> {
+    // Evaluated wherever a storage or mutable scanner is created, so that a component type which is
+    // not zero-sized is a compile error.
+    const ZERO_SIZED: () = assert!(
+        mem::size_of::<T>() == 0,
+        "FlagComponentStorage requires a zero-sized component type"
+    );
+}
+
+impl<T: Default> Default for FlagComponentStorage<T> {
+    fn default() -> FlagComponentStorage<T> {
+        let () = Self::ZERO_SIZED;
+        FlagComponentStorage {
+            set: HierarchicalBitSet::new(),
+            flag: T::default(),
+        }
+    }
+}
+
+// Flags are often sparse, and dense ticks would be far larger than the bit set of flags.
+impl<T> TickedStorage for FlagComponentStorage<T> {
+    type Ticks = SparseComponentStorage<ComponentTicks>;
+}
+
+impl<'a, T: 'static + Send + Sync + Clone + Default> ComponentStorage<'a>
+    for FlagComponentStorage<T>
+{
+    type Component = T;
+    type Scan = FlagComponentScanner<'a, T>;
+    type ScanMut = FlagComponentScannerMut<'a, T>;
+
+    fn get(&self, index: usize) -> Option<&T> {
+        if self.set.contains(index) {
+            Some(&self.flag)
+        } else {
+            None
+        }
+    }
+
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if self.set.contains(index) {
+            Some(&mut self.flag)
+        } else {
+            None
+        }
+    }
+
+    fn insert(&mut self, index: usize, component: T) -> Option<T> {
+        if self.set.insert(index) {
+            None
+        } else {
+            // All values of a zero-sized type are the same, so the given component can stand in for
+            // the one it replaces.
+            Some(component)
+        }
+    }
+
+    fn remove(&mut self, index: usize) -> Option<T> {
+        if self.set.remove(index) {
+            Some(self.flag.clone())
+        } else {
+            None
+        }
+    }
+
+    fn scan(&'a self) -> Self::Scan {
+        self.scan_range(0..usize::MAX)
+    }
+
+    fn scan_mut(&'a mut self) -> Self::ScanMut {
+        self.scan_mut_range(0..usize::MAX)
+    }
+
+    fn scan_range(&'a self, range: Range<usize>) -> Self::Scan {
+        FlagComponentScanner {
+            next_index: range.start,
+            end: range.end,
+            set: &self.set,
+            flag: &self.flag,
+        }
+    }
+
+    fn scan_mut_range(&'a mut self, range: Range<usize>) -> Self::ScanMut {
+        let () = Self::ZERO_SIZED;
+        FlagComponentScannerMut {
+            next_index: range.start,
+            end: range.end,
+            set: &self.set,
+            flag: PhantomData,
+        }
+    }
+
+    fn estimated_len(&self) -> usize {
+        self.set.len()
+    }
+}
+
+impl<T: 'static + Default> FlagComponentStorage<T> {
+    pub fn new() -> FlagComponentStorage<T> {
+        FlagComponentStorage::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+}
+
+impl<'a, T> ComponentScanner for FlagComponentScanner<'a, T> {
+    type Item = &'a T;
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(&'a T, usize)> {
+        let index = self
+            .set
+            .next(cmp::max(self.next_index, until.unwrap_or(0)))
+            .filter(|&index| index < self.end)?;
+        self.next_index = index + 1;
+        Some((self.flag, index))
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(remaining_len(self.set, self.next_index, self.end))
+    }
+}
+
+impl<'a, T> ComponentScanner for FlagComponentScannerMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(&'a mut T, usize)> {
+        let index = self
+            .set
+            .next(cmp::max(self.next_index, until.unwrap_or(0)))
+            .filter(|&index| index < self.end)?;
+        self.next_index = index + 1;
+        // Every returned tag needs to be a distinct mutable reference, which for a zero-sized type
+        // can point anywhere non-null and aligned.
+        // SAFETY: T is zero-sized, checked by `ZERO_SIZED` when this scanner was created, so a
+        // dangling pointer is valid for reads and writes of T and the reference aliases no memory.
+        // A value of T may be freely created, since the storage requires `T: Default`.
+        Some((unsafe { &mut *NonNull::<T>::dangling().as_ptr() }, index))
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(remaining_len(self.set, self.next_index, self.end))
+    }
+}
+
+impl<'a, T> SplitScanner for FlagComponentScanner<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        index_range(self.set, self.next_index, self.end)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let lower = FlagComponentScanner {
+            end: cmp::min(index, self.end),
+            ..self
+        };
+        let upper = FlagComponentScanner {
+            next_index: cmp::max(self.next_index, index),
+            ..self
+        };
+        (lower, upper)
+    }
+}
+
+impl<'a, T> SplitScanner for FlagComponentScannerMut<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        index_range(self.set, self.next_index, self.end)
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let lower = FlagComponentScannerMut {
+            end: cmp::min(index, self.end),
+            ..self
+        };
+        let upper = FlagComponentScannerMut {
+            next_index: cmp::max(self.next_index, index),
+            ..self
+        };
+        (lower, upper)
+    }
+}
+
+fn index_range(set: &HierarchicalBitSet, next_index: usize, end: usize) -> Range<usize> {
+    let end = cmp::min(end, set.capacity());
+    match set.next(next_index) {
+        Some(start) if start < end => start..end,
+        _ => 0..0,
+    }
+}
+
+fn remaining_len(set: &HierarchicalBitSet, next_index: usize, end: usize) -> usize {
+    cmp::min(set.len(), end.saturating_sub(next_index))
+}
@@ -1,4 +1,5 @@
 use std::iter::FromIterator;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{iter, slice, vec};
 
 /// A unique identifier with an associated usize index.  Indexes are valued proportional to the
@@ -14,10 +15,15 @@ pub struct GenerationalIndex {
 }
 
 /// Allocates GenerationalIndexes without duplication.
-#[derive(Clone, Default)]
+///
+/// Indexes can also be reserved through a shared reference with `reserve`.  Reserved indexes are
+/// not live until they are allocated with `allocate_reserved`, which every other allocation or
+/// deallocation does first.
+#[derive(Default)]
 pub struct GenerationalIndexAllocator {
     entries: Vec<AllocatorEntry>,
     free: Vec<usize>,
+    reserved: AtomicUsize,
 }
 
 /// An associative array of GenerationalIndex keys to values.  Takes advantage of how
@@ -59,18 +65,29 @@ impl GenerationalIndex {
     }
 }
 
+impl Clone for GenerationalIndexAllocator {
+    fn clone(&self) -> GenerationalIndexAllocator {
+        GenerationalIndexAllocator {
+            entries: self.entries.clone(),
+            free: self.free.clone(),
+            reserved: AtomicUsize::new(self.reserved.load(Ordering::Relaxed)),
+        }
+    }
+}
+
 impl GenerationalIndexAllocator {
     pub fn new() -> GenerationalIndexAllocator {
         Default::default()
     }
 
     pub fn allocate(&mut self) -> GenerationalIndex {
+        self.allocate_reserved();
         if let Some(index) = self.free.pop() {
             let id_entry = &mut self.entries[index];
             assert!(!id_entry.is_live);
             id_entry.is_live = true;
             GenerationalIndex {
-                index: index,
+                index,
                 generation: id_entry.generation,
             }
         } else {
@@ -86,6 +103,7 @@ impl GenerationalIndexAllocator {
     }
 
     pub fn deallocate(&mut self, gen_index: GenerationalIndex) -> bool {
+        self.allocate_reserved();
         if gen_index.index >= self.entries.len() {
             return false;
         }
@@ -104,6 +122,45 @@ impl GenerationalIndexAllocator {
         true
     }
 
+    /// Reserves a GenerationalIndex that will be returned by no other call to `allocate` or
+    /// `reserve`.  Reserved indexes take from the free list in the same order as `allocate`, and
+    /// then from past the end of the allocated indexes.
+    pub fn reserve(&self) -> GenerationalIndex {
+        let n = self.reserved.fetch_add(1, Ordering::Relaxed);
+        if n < self.free.len() {
+            let index = self.free[self.free.len() - 1 - n];
+            GenerationalIndex {
+                index,
+                generation: self.entries[index].generation,
+            }
+        } else {
+            GenerationalIndex {
+                index: self.entries.len() + (n - self.free.len()),
+                generation: 0,
+            }
+        }
+    }
+
+    /// Makes every reserved GenerationalIndex live.
+    pub fn allocate_reserved(&mut self) {
+        let reserved = self.reserved.get_mut();
+        let count = *reserved;
+        *reserved = 0;
+
+        for _ in 0..count {
+            if let Some(index) = self.free.pop() {
+                let id_entry = &mut self.entries[index];
+                assert!(!id_entry.is_live);
+                id_entry.is_live = true;
+            } else {
+                self.entries.push(AllocatorEntry {
+                    is_live: true,
+                    generation: 0,
+                });
+            }
+        }
+    }
+
     #[inline]
     pub fn is_live(&self, gen_index: GenerationalIndex) -> bool {
         if gen_index.index < self.entries.len() {
@@ -279,8 +336,8 @@ impl<'a, T: 'a> Iterator for GenerationalIndexArrayIter<'a, T> {
     type Item = (GenerationalIndex, &'a T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((index, entry)) = self.0.next() {
-            if let &Some(ref entry) = entry {
+        for (index, entry) in self.0.by_ref() {
+            if let Some(entry) = entry {
                 return Some((
                     GenerationalIndex {
                         index,
@@ -298,7 +355,7 @@ impl<'a, T: 'a> Iterator for GenerationalIndexArrayIterMut<'a, T> {
     type Item = (GenerationalIndex, &'a mut T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((index, entry)) = self.0.next() {
+        for (index, entry) in self.0.by_ref() {
             if let &mut Some(ref mut entry) = entry {
                 return Some((
                     GenerationalIndex {
@@ -317,7 +374,7 @@ impl<T> Iterator for GenerationalIndexArrayIntoIter<T> {
     type Item = (GenerationalIndex, T);
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((index, entry)) = self.0.next() {
+        for (index, entry) in self.0.by_ref() {
             if let Some(entry) = entry {
                 return Some((
                     GenerationalIndex {
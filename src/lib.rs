@@ -1,18 +1,39 @@
+// The `Fail` derive from failure_derive generates impls inside of an anonymous const.
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate failure;
 extern crate anymap;
 #[macro_use]
 extern crate downcast_rs;
 
+pub mod bit_set;
+#[macro_use]
+pub mod bundle;
+pub mod change_tick;
+pub mod command_buffer;
 pub mod component;
+pub mod component_hooks;
 pub mod component_scanner;
 pub mod dense_component;
 pub mod ecs;
 pub mod entity;
+pub mod error;
+pub mod flag_component;
 pub mod generational_index;
+pub mod lock_tracker;
+pub mod lock_wait;
+pub mod parallel_scan;
+pub mod query;
+pub mod removal_log;
+pub mod shared_read_guard;
 pub mod sparse_component;
+pub mod sparse_set_component;
+pub mod system;
 pub mod world;
 pub mod world_multi_lock;
 
+pub use error::Error;
+
 #[cfg(test)]
 mod tests;
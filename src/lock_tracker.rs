@@ -0,0 +1,66 @@
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+
+use lock_wait::LockWait;
+use world_multi_lock::LockId;
+
+#[cfg(debug_assertions)]
+thread_local! {
+    static HELD_LOCKS: RefCell<Vec<(LockId, &'static str)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Records that the current thread holds a component storage or resource lock, for as long as
+/// it is alive, so that taking locks in an order that could deadlock is caught in debug builds.
+/// In release builds this records nothing.
+///
+/// A blocking lock panics if the thread already holds the same lock, or holds a lock that comes
+/// after it in `LockId` order.  Locks that do not block forever, with `LockWait::Try` or
+/// `LockWait::Until`, cannot deadlock and so are never checked.
+pub struct HeldLock {
+    #[cfg(debug_assertions)]
+    id: LockId,
+}
+
+impl HeldLock {
+    /// Must be called before waiting for the lock, so that a deadlock panics instead of hanging.
+    #[cfg(debug_assertions)]
+    pub fn acquire(id: LockId, type_name: &'static str, wait: LockWait) -> HeldLock {
+        HELD_LOCKS.with(|held| {
+            let mut held = held.borrow_mut();
+            if let LockWait::Block = wait {
+                for &(held_id, held_name) in held.iter() {
+                    if held_id == id {
+                        panic!("lock on {} re-acquired by the same thread", type_name);
+                    } else if held_id > id {
+                        panic!(
+                            "lock on {} acquired while holding lock on {}, which must be \
+                             acquired after it",
+                            type_name, held_name
+                        );
+                    }
+                }
+            }
+            held.push((id, type_name));
+        });
+        HeldLock { id }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn acquire(_: LockId, _: &'static str, _: LockWait) -> HeldLock {
+        HeldLock {}
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Drop for HeldLock {
+    fn drop(&mut self) {
+        // Ignores a thread local that is already destroyed, which can only happen to locks held
+        // by other thread locals.
+        let _ = HELD_LOCKS.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(pos) = held.iter().rposition(|&(id, _)| id == self.id) {
+                held.remove(pos);
+            }
+        });
+    }
+}
@@ -0,0 +1,56 @@
+use std::cmp;
+use std::sync::{LockResult, TryLockError, TryLockResult};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use error::Error;
+
+/// How long to wait for a lock on a component storage or resource that is held elsewhere.
+#[derive(Clone, Copy, Debug)]
+pub enum LockWait {
+    /// Block until the lock is acquired.
+    Block,
+    /// Return `Error::WouldBlock` immediately.
+    Try,
+    /// Return `Error::WouldBlock` if the lock is not acquired by the given instant.
+    Until(Instant),
+}
+
+impl LockWait {
+    /// Waits for at most the given duration from now.
+    pub fn timeout(timeout: Duration) -> LockWait {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => LockWait::Until(deadline),
+            None => LockWait::Block,
+        }
+    }
+
+    // There is no way to wait on a std `RwLock` with a timeout, so waiting until a deadline polls
+    // `try_lock`.
+    pub(crate) fn acquire<T, G, L, TL>(self, lock: L, mut try_lock: TL) -> Result<G, Error>
+    where
+        T: 'static,
+        L: FnOnce() -> LockResult<G>,
+        TL: FnMut() -> TryLockResult<G>,
+    {
+        let deadline = match self {
+            LockWait::Block => return lock().map_err(|_| Error::lock_poisoned::<T>()),
+            LockWait::Try => Instant::now(),
+            LockWait::Until(deadline) => deadline,
+        };
+
+        loop {
+            match try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(_)) => return Err(Error::lock_poisoned::<T>()),
+                Err(TryLockError::WouldBlock) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::would_block::<T>());
+                    }
+                    thread::sleep(cmp::min(deadline - now, Duration::from_millis(1)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::cmp;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::thread;
+
+use component_scanner::ComponentScanner;
+
+/// A ComponentScanner that can be split into scanners over disjoint ranges of indexes, which can
+/// then be advanced independently of each other, on different threads.
+pub trait SplitScanner: ComponentScanner + Sized {
+    /// A range containing the index of every entry that this scanner has left to return.  The end
+    /// is `usize::MAX` for scanners that never end.
+    fn index_range(&self) -> Range<usize>;
+
+    /// Splits this scanner into a scanner of its remaining entries below the given index, and a
+    /// scanner of its remaining entries at or above it.
+    fn split_at(self, index: usize) -> (Self, Self);
+}
+
+/// Processes the items of a `SplitScanner` on multiple threads, returned by
+/// `ComponentScanner::par_scan`.
+///
+/// The index range of the scanner is split into parts of `batch_size` consecutive indexes, each
+/// with its own scanner.  The worker threads and the calling thread each take the next part that
+/// nobody has taken yet and scan it to completion, until every part is done, so no thread ever waits
+/// on another thread's scanning.  A scanner that never ends is processed on the calling thread.
+pub struct ParallelScan<S> {
+    scanner: S,
+    thread_count: usize,
+    batch_size: usize,
+}
+
+impl<S: SplitScanner> ParallelScan<S> {
+    /// Uses as many threads as there is available parallelism, and batches of 1024 indexes.
+    pub fn new(scanner: S) -> ParallelScan<S> {
+        ParallelScan {
+            scanner,
+            thread_count: thread::available_parallelism().map_or(1, |n| n.get()),
+            batch_size: 1024,
+        }
+    }
+
+    /// The total number of threads to use, including the calling thread.
+    pub fn thread_count(mut self, thread_count: usize) -> Self {
+        self.thread_count = thread_count.max(1);
+        self
+    }
+
+    /// The number of consecutive indexes that a thread scans at a time.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Calls the given function with every scanned item and its index, in no particular order.
+    pub fn for_each<F>(self, f: F)
+    where
+        S: Send,
+        F: Fn(S::Item, usize) + Sync,
+    {
+        let run = |mut scanner: S| {
+            while let Some((item, index)) = scanner.scan(None) {
+                f(item, index);
+            }
+        };
+
+        let range = self.scanner.index_range();
+        if self.thread_count == 1 || range.end == usize::MAX || range.end <= range.start {
+            run(self.scanner);
+            return;
+        }
+
+        // Splitting from the end means every split only moves the entries above the split point,
+        // and leaves the lowest part at the end of `parts`, so it is taken first.
+        let mut parts = Vec::new();
+        let mut rest = self.scanner;
+        let mut boundary =
+            range.start + (range.end - range.start - 1) / self.batch_size * self.batch_size;
+        while boundary > range.start {
+            let (lower, upper) = rest.split_at(boundary);
+            parts.push(upper);
+            rest = lower;
+            boundary -= self.batch_size;
+        }
+        parts.push(rest);
+
+        let thread_count = cmp::min(self.thread_count, parts.len());
+        let parts = Mutex::new(parts);
+        let run_parts = || loop {
+            let part = parts.lock().unwrap().pop();
+            match part {
+                Some(part) => run(part),
+                None => return,
+            }
+        };
+
+        thread::scope(|scope| {
+            for _ in 1..thread_count {
+                scope.spawn(run_parts);
+            }
+            run_parts();
+        });
+    }
+}
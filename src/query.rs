@@ -0,0 +1,408 @@
+use std::cmp;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use change_tick::{TrackedMut, TrackedScanMut};
+use component::{Component, ComponentStorage};
+use component_scanner::{
+    ComponentScanner, ComponentScannerIterator, ComponentScannerMap, ComponentScannerOpt,
+    ComponentScannerTuple,
+};
+use ecs::{ComponentReadHandle, ComponentWriteHandle};
+use entity::{Entity, EntityScanner};
+use error::Error;
+use lock_wait::LockWait;
+use parallel_scan::SplitScanner;
+use world::World;
+use world_multi_lock::{
+    LockAccess, LockId, LockerRef, ReadComponent, WorldLocker, WorldMultiLocker, WriteComponent,
+};
+
+/// A set of component handles locked from a `World` with `World::query`, which scans the joined
+/// items of every term in `Q` at once.
+///
+/// `Q` is a tuple of query terms:
+///   * `&T` for a read only reference to component `T`
+///   * `&mut T` for a mutable reference to component `T`
+///   * `Option<&T>` or `Option<&mut T>` for a component which may or may not be present
+///   * `Without<T>` to only match entities that do not have component `T`, yields `()`
+///   * `Entity` for the entity which owns the rest of the components
+///
+/// Only live entities are ever matched, so a query with only optional terms is still bounded.
+pub struct Query<'a, Q: QueryTerms<'a>> {
+    world: &'a World,
+    handles: Q::Handles,
+}
+
+/// Query term which only matches entities that do NOT have component `T`.
+pub struct Without<T>(PhantomData<T>);
+
+/// A single element of the tuple passed to `World::query`.
+pub trait QueryTerm<'a> {
+    type Locker: Default;
+    type Handle;
+
+    fn lockers<'b>(
+        locker: &'b Self::Locker,
+        lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+    );
+    // Will panic unless all lockers have been called
+    fn handle(locker: Self::Locker) -> Self::Handle;
+}
+
+pub trait QueryTermScan<'a, 'b>: QueryTerm<'a> {
+    type Scan: ComponentScanner;
+
+    fn scan(handle: &'b mut Self::Handle, world: &'a World) -> Self::Scan;
+}
+
+/// Implemented for tuples of `QueryTerm`.
+pub trait QueryTerms<'a> {
+    type Lockers: Default;
+    type Handles;
+
+    fn lockers<'b>(lockers: &'b Self::Lockers) -> Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>;
+    fn handles(lockers: Self::Lockers) -> Self::Handles;
+}
+
+pub trait QueryTermsScan<'a, 'b>: QueryTerms<'a> {
+    type Scan: ComponentScanner;
+
+    fn scan(handles: &'b mut Self::Handles, world: &'a World) -> Self::Scan;
+}
+
+impl World {
+    /// Lock every component used by the query terms in `Q`, in the same order as `multi_lock`.
+    pub fn query<'a, Q: QueryTerms<'a>>(&'a self) -> Result<Query<'a, Q>, Error> {
+        let handles =
+            QueryLocker::<'a, Q>(Q::Lockers::default(), PhantomData).lock(self, LockWait::Block)?;
+        Ok(Query {
+            world: self,
+            handles,
+        })
+    }
+}
+
+impl<'a, Q: QueryTerms<'a>> Query<'a, Q> {
+    pub fn scan<'b>(&'b mut self) -> <Q as QueryTermsScan<'a, 'b>>::Scan
+    where
+        Q: QueryTermsScan<'a, 'b>,
+    {
+        Q::scan(&mut self.handles, self.world)
+    }
+
+    pub fn iter<'b>(&'b mut self) -> ComponentScannerIterator<<Q as QueryTermsScan<'a, 'b>>::Scan>
+    where
+        Q: QueryTermsScan<'a, 'b>,
+    {
+        self.scan().iter()
+    }
+}
+
+impl<'a, 'b, Q: QueryTermsScan<'a, 'b>> IntoIterator for &'b mut Query<'a, Q> {
+    type Item = <Q::Scan as ComponentScanner>::Item;
+    type IntoIter = ComponentScannerIterator<Q::Scan>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+struct QueryLocker<'a, Q: QueryTerms<'a>>(Q::Lockers, PhantomData<fn(&'a World) -> Q>);
+
+impl<'a, Q: QueryTerms<'a>> WorldMultiLocker<'a> for QueryLocker<'a, Q> {
+    type Handles = Q::Handles;
+
+    fn lockers<'b>(&'b self) -> Vec<(LockId, LockAccess, LockerRef<'a, 'b>)> {
+        Q::lockers(&self.0)
+    }
+
+    fn handles(self) -> Self::Handles {
+        Q::handles(self.0)
+    }
+}
+
+fn push_locker<'a, 'b, L: WorldLocker<'a>>(
+    locker: &'b L,
+    lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+) {
+    lockers.push((locker.id(), locker.access(), locker));
+}
+
+impl<'a, T: Component> QueryTerm<'a> for &T {
+    type Locker = ReadComponent<'a, T>;
+    type Handle = ComponentReadHandle<'a, T>;
+
+    fn lockers<'b>(
+        locker: &'b Self::Locker,
+        lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+    ) {
+        push_locker(locker, lockers);
+    }
+
+    fn handle(locker: Self::Locker) -> Self::Handle {
+        locker.handle()
+    }
+}
+
+impl<'a, 'b, T: Component> QueryTermScan<'a, 'b> for &T {
+    type Scan = <T::Storage as ComponentStorage<'b>>::Scan;
+
+    fn scan(handle: &'b mut Self::Handle, _: &'a World) -> Self::Scan {
+        handle.scan()
+    }
+}
+
+impl<'a, T: Component> QueryTerm<'a> for &mut T {
+    type Locker = WriteComponent<'a, T>;
+    type Handle = ComponentWriteHandle<'a, T>;
+
+    fn lockers<'b>(
+        locker: &'b Self::Locker,
+        lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+    ) {
+        push_locker(locker, lockers);
+    }
+
+    fn handle(locker: Self::Locker) -> Self::Handle {
+        locker.handle()
+    }
+}
+
+impl<'a, 'b, T: Component> QueryTermScan<'a, 'b> for &mut T {
+    type Scan = TrackedScanMut<'b, T>;
+
+    fn scan(handle: &'b mut Self::Handle, _: &'a World) -> Self::Scan {
+        handle.scan_mut()
+    }
+}
+
+impl<'a, T: Component> QueryTerm<'a> for Option<&T> {
+    type Locker = ReadComponent<'a, T>;
+    type Handle = ComponentReadHandle<'a, T>;
+
+    fn lockers<'b>(
+        locker: &'b Self::Locker,
+        lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+    ) {
+        push_locker(locker, lockers);
+    }
+
+    fn handle(locker: Self::Locker) -> Self::Handle {
+        locker.handle()
+    }
+}
+
+impl<'a, 'b, T: Component> QueryTermScan<'a, 'b> for Option<&T> {
+    type Scan = ComponentScannerOpt<
+        <T::Storage as ComponentStorage<'b>>::Scan,
+        <<T::Storage as ComponentStorage<'b>>::Scan as ComponentScanner>::Item,
+    >;
+
+    fn scan(handle: &'b mut Self::Handle, _: &'a World) -> Self::Scan {
+        handle.scan_opt()
+    }
+}
+
+impl<'a, T: Component> QueryTerm<'a> for Option<&mut T> {
+    type Locker = WriteComponent<'a, T>;
+    type Handle = ComponentWriteHandle<'a, T>;
+
+    fn lockers<'b>(
+        locker: &'b Self::Locker,
+        lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+    ) {
+        push_locker(locker, lockers);
+    }
+
+    fn handle(locker: Self::Locker) -> Self::Handle {
+        locker.handle()
+    }
+}
+
+impl<'a, 'b, T: Component> QueryTermScan<'a, 'b> for Option<&mut T> {
+    type Scan = ComponentScannerOpt<TrackedScanMut<'b, T>, TrackedMut<'b, T>>;
+
+    fn scan(handle: &'b mut Self::Handle, _: &'a World) -> Self::Scan {
+        handle.scan_mut_opt()
+    }
+}
+
+impl<'a, T: Component> QueryTerm<'a> for Without<T> {
+    type Locker = ReadComponent<'a, T>;
+    type Handle = ComponentReadHandle<'a, T>;
+
+    fn lockers<'b>(
+        locker: &'b Self::Locker,
+        lockers: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>,
+    ) {
+        push_locker(locker, lockers);
+    }
+
+    fn handle(locker: Self::Locker) -> Self::Handle {
+        locker.handle()
+    }
+}
+
+impl<'a, 'b, T: Component> QueryTermScan<'a, 'b> for Without<T> {
+    type Scan = WithoutScanner<<T::Storage as ComponentStorage<'b>>::Scan>;
+
+    fn scan(handle: &'b mut Self::Handle, _: &'a World) -> Self::Scan {
+        WithoutScanner::new(handle.scan())
+    }
+}
+
+impl<'a> QueryTerm<'a> for Entity {
+    type Locker = ();
+    type Handle = ();
+
+    fn lockers<'b>(_: &'b Self::Locker, _: &mut Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>) {}
+
+    fn handle(_: Self::Locker) -> Self::Handle {}
+}
+
+impl<'a, 'b> QueryTermScan<'a, 'b> for Entity {
+    type Scan = EntityScanner<'a>;
+
+    fn scan(_: &'b mut Self::Handle, world: &'a World) -> Self::Scan {
+        world.scan_entities()
+    }
+}
+
+/// Returns `()` for every index that the wrapped scanner does NOT have a value for.  This never
+/// ends on its own, so it must be joined with a bounded scanner.
+pub struct WithoutScanner<S> {
+    scanner: S,
+    current: usize,
+    // Only below `usize::MAX` for the lower halves of split scanners.
+    end: usize,
+    next: Option<usize>,
+}
+
+impl<S: ComponentScanner> WithoutScanner<S> {
+    fn new(mut scanner: S) -> WithoutScanner<S> {
+        let next = scanner.scan(None).map(|(_, i)| i);
+        WithoutScanner {
+            scanner,
+            current: 0,
+            end: usize::MAX,
+            next,
+        }
+    }
+}
+
+impl<S: ComponentScanner> ComponentScanner for WithoutScanner<S> {
+    type Item = ();
+
+    fn scan(&mut self, until: Option<usize>) -> Option<((), usize)> {
+        self.current = cmp::max(self.current, until.unwrap_or(0));
+        loop {
+            if self.current >= self.end {
+                return None;
+            }
+            match self.next {
+                Some(next) if next < self.current => {
+                    self.next = self.scanner.scan(Some(self.current)).map(|(_, i)| i);
+                }
+                Some(next) if next == self.current => {
+                    self.current += 1;
+                }
+                _ => {
+                    let index = self.current;
+                    self.current += 1;
+                    return Some(((), index));
+                }
+            }
+        }
+    }
+
+    fn is_bounded(&self) -> bool {
+        self.end != usize::MAX
+    }
+}
+
+impl<S: SplitScanner> SplitScanner for WithoutScanner<S> {
+    fn index_range(&self) -> Range<usize> {
+        self.current..self.end
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = cmp::max(self.current, cmp::min(index, self.end));
+        let (lower, mut upper) = self.scanner.split_at(mid);
+        let (lower_next, upper_next) = match self.next {
+            Some(next) if next < mid => (Some(next), upper.scan(None).map(|(_, i)| i)),
+            next => (None, next),
+        };
+        let lower = WithoutScanner {
+            scanner: lower,
+            current: self.current,
+            end: mid,
+            next: lower_next,
+        };
+        let upper = WithoutScanner {
+            scanner: upper,
+            current: mid,
+            end: self.end,
+            next: upper_next,
+        };
+        (lower, upper)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($term:ident)*) => (
+        impl<'a, $($term,)*> QueryTerms<'a> for ($($term,)*)
+            where $($term: QueryTerm<'a>,)*
+        {
+            type Lockers = ($($term::Locker,)*);
+            type Handles = ($($term::Handle,)*);
+
+            #[allow(non_snake_case)]
+            fn lockers<'b>(lockers: &'b Self::Lockers) -> Vec<(LockId, LockAccess, LockerRef<'a, 'b>)> {
+                let mut locker_refs = Vec::new();
+                let ($(ref $term,)*) = *lockers;
+                $($term::lockers($term, &mut locker_refs);)*
+                locker_refs
+            }
+
+            #[allow(non_snake_case)]
+            fn handles(lockers: Self::Lockers) -> Self::Handles {
+                let ($($term,)*) = lockers;
+                ($($term::handle($term),)*)
+            }
+        }
+
+        impl<'a, 'b, $($term,)*> QueryTermsScan<'a, 'b> for ($($term,)*)
+            where $($term: QueryTermScan<'a, 'b>,)*
+        {
+            type Scan = ComponentScannerMap<
+                <(EntityScanner<'a>, $($term::Scan,)*) as ComponentScannerTuple>::JoinScanner,
+                fn((Entity, $(<$term::Scan as ComponentScanner>::Item,)*))
+                    -> ($(<$term::Scan as ComponentScanner>::Item,)*)
+            >;
+
+            #[allow(non_snake_case)]
+            fn scan(handles: &'b mut Self::Handles, world: &'a World) -> Self::Scan {
+                let ($(ref mut $term,)*) = *handles;
+                // Joining with every live entity keeps queries of only optional or `Without` terms
+                // bounded.
+                (world.scan_entities(), $($term::scan($term, world),)*)
+                    .join()
+                    .map(|(_, $($term,)*)| ($($term,)*))
+            }
+        }
+    );
+}
+
+impl_tuple! {A}
+impl_tuple! {A B}
+impl_tuple! {A B C}
+impl_tuple! {A B C D}
+impl_tuple! {A B C D E}
+impl_tuple! {A B C D E F}
+impl_tuple! {A B C D E F G}
+impl_tuple! {A B C D E F G H}
+impl_tuple! {A B C D E F G H I}
+impl_tuple! {A B C D E F G H I J}
+impl_tuple! {A B C D E F G H I J K}
+impl_tuple! {A B C D E F G H I J K L}
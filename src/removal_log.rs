@@ -0,0 +1,96 @@
+use std::collections::{vec_deque, VecDeque};
+
+use entity::Entity;
+
+/// A log of the components removed from a single component storage, which is only kept for
+/// component types that opt in with `Ecs::track_removals`.  Records the entity each component was
+/// removed from, and optionally the removed component itself.
+///
+/// Any number of readers can read the log independently, each with its own `RemovalReader` cursor.
+/// The log is trimmed by `trim`, called from `World::maintain`, which only drops entries that were
+/// already in the log at the previous trim, so a reader that reads at least once between every
+/// two trims never misses an entry.
+#[derive(Clone)]
+pub struct RemovalLog<T> {
+    entries: VecDeque<(Entity, Option<T>)>,
+    // The position of the first entry in `entries` among every entry ever logged.
+    start: usize,
+    // The end of the log as of the previous trim.
+    trim_end: usize,
+    keep_values: bool,
+}
+
+/// A cursor into a `RemovalLog`, which only sees entries logged after it was created.
+#[derive(Clone, Copy, Debug)]
+pub struct RemovalReader(usize);
+
+pub struct RemovalLogIter<'a, T: 'a>(vec_deque::Iter<'a, (Entity, Option<T>)>);
+
+impl<T> RemovalLog<T> {
+    pub fn new(keep_values: bool) -> RemovalLog<T> {
+        RemovalLog {
+            entries: VecDeque::new(),
+            start: 0,
+            trim_end: 0,
+            keep_values,
+        }
+    }
+
+    pub fn keeps_values(&self) -> bool {
+        self.keep_values
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Creates a reader that will see every entry logged from now on.
+    pub fn reader(&self) -> RemovalReader {
+        RemovalReader(self.end())
+    }
+
+    /// Returns every entry the reader has not yet seen and advances it past them.  If the reader
+    /// fell behind far enough that entries it had not seen were trimmed, those entries are skipped.
+    pub fn read(&self, reader: &mut RemovalReader) -> RemovalLogIter<'_, T> {
+        let skip = reader.0.saturating_sub(self.start).min(self.entries.len());
+        reader.0 = self.end();
+        RemovalLogIter(self.entries.range(skip..))
+    }
+
+    /// Logs the removal of a component from an entity.  The component is only produced by the
+    /// given function if this log keeps values.
+    pub fn push<F: FnOnce() -> T>(&mut self, entity: Entity, component: F) {
+        let component = if self.keep_values {
+            Some(component())
+        } else {
+            None
+        };
+        self.entries.push_back((entity, component));
+    }
+
+    /// Drops every entry that was logged before the previous call to `trim`.
+    pub fn trim(&mut self) {
+        let count = self.trim_end.saturating_sub(self.start);
+        self.entries.drain(..count);
+        self.start += count;
+        self.trim_end = self.end();
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.entries.len()
+    }
+}
+
+impl<'a, T: 'a> Iterator for RemovalLogIter<'a, T> {
+    type Item = (Entity, Option<&'a T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|(entity, value)| (*entity, value.as_ref()))
+    }
+}
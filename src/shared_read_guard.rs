@@ -0,0 +1,88 @@
+use std::any::Any;
+use std::ops::Deref;
+use std::sync::{Arc, RwLockReadGuard};
+
+use lock_tracker::HeldLock;
+
+/// A read lock on a component storage or resource that can be shared by several handles, so that
+/// a thread that reads the same lock twice never waits for a lock that it already holds.  A std
+/// `RwLock` blocks new readers while a writer is waiting, so waiting for a second read could
+/// deadlock.
+///
+/// A guard owns its lock until `into_shared` is called, which only `World::multi_lock` does for a
+/// lock that is read more than once.  Like a plain `RwLockReadGuard`, the guard can be shared
+/// between threads but must be dropped by the thread that locked it.
+pub struct SharedReadGuard<'a, T: 'a>(Lock<'a, T>);
+
+/// A `SharedReadGuard` with its type erased, so that lockers of different types can share it.
+#[derive(Clone)]
+pub struct SharedReadLock<'a>(Arc<dyn ErasedReadGuard + 'a>);
+
+enum Lock<'a, T: 'a> {
+    Owned(ReadGuard<'a, T>),
+    Shared(SharedReadLock<'a>),
+}
+
+// SAFETY: A `&SharedReadGuard` only gives other threads `&T` through `deref`.  The shared lock is
+// only ever cloned by `into_shared`, which takes the guard by value, and neither the guard nor a
+// `SharedReadLock` is `Send`, so every clone and drop of the lock happens on the thread that
+// acquired it.
+unsafe impl<'a, T: Sync> Sync for SharedReadGuard<'a, T> {}
+
+trait ErasedReadGuard: Sync {
+    fn get(&self) -> &dyn Any;
+}
+
+struct ReadGuard<'a, T: 'a> {
+    guard: RwLockReadGuard<'a, T>,
+    _held: HeldLock,
+}
+
+impl<'a, T: Any + Sync> ErasedReadGuard for ReadGuard<'a, T> {
+    fn get(&self) -> &dyn Any {
+        &*self.guard
+    }
+}
+
+impl<'a, T> SharedReadGuard<'a, T> {
+    pub(crate) fn new(guard: RwLockReadGuard<'a, T>, held: HeldLock) -> SharedReadGuard<'a, T> {
+        SharedReadGuard(Lock::Owned(ReadGuard { guard, _held: held }))
+    }
+}
+
+impl<'a, T: Any + Sync> SharedReadGuard<'a, T> {
+    /// Returns the guard along with its lock, for other guards of the same lock to share.
+    pub(crate) fn into_shared(self) -> (SharedReadGuard<'a, T>, SharedReadLock<'a>) {
+        let lock = match self.0 {
+            Lock::Owned(guard) => SharedReadLock(Arc::new(guard)),
+            Lock::Shared(lock) => lock,
+        };
+        (SharedReadGuard(Lock::Shared(lock.clone())), lock)
+    }
+}
+
+impl<'a, T: Any> SharedReadGuard<'a, T> {
+    /// Panics if the lock is not a lock on a `T`.
+    pub(crate) fn from_shared(lock: SharedReadLock<'a>) -> SharedReadGuard<'a, T> {
+        assert!(
+            lock.0.get().is::<T>(),
+            "shared read lock has the wrong type"
+        );
+        SharedReadGuard(Lock::Shared(lock))
+    }
+}
+
+impl<'a, T: Any> Deref for SharedReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.0 {
+            Lock::Owned(ref guard) => &guard.guard,
+            Lock::Shared(ref lock) => lock
+                .0
+                .get()
+                .downcast_ref()
+                .expect("shared read lock has the wrong type"),
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use std::cmp;
 use std::collections::btree_map;
 use std::collections::BTreeMap;
+use std::ops::Range;
 
+use change_tick::{ComponentTicks, TickedStorage};
 use component::ComponentStorage;
 use component_scanner::ComponentScanner;
+use parallel_scan::SplitScanner;
 
 #[derive(Clone)]
 pub struct SparseComponentStorage<T>(BTreeMap<usize, T>);
@@ -10,9 +14,25 @@ pub struct SparseComponentStorage<T>(BTreeMap<usize, T>);
 pub struct SparseComponentScanner<'a, T: 'a> {
     map: &'a BTreeMap<usize, T>,
     iter: btree_map::Range<'a, usize, T>,
+    // No entry below `start` is left to return.
+    start: usize,
+    end: usize,
 }
 
-pub struct SparseComponentScannerMut<'a, T: 'a>(btree_map::IterMut<'a, usize, T>);
+/// The mutable range borrows the map for as long as the scanner lives, so the map cannot be ranged
+/// again and seeking steps forward through the range instead.  Over a whole scan this visits each
+/// entry in the range once, like a scan that never seeks.
+pub struct SparseComponentScannerMut<'a, T: 'a> {
+    iter: Option<btree_map::RangeMut<'a, usize, T>>,
+    // Entries taken off the back of `iter` by `split_at`, all above any entry left in `iter`, in
+    // descending index order.
+    split: Vec<(usize, &'a mut T)>,
+    // An upper bound on the number of entries left in `iter`.
+    iter_len: usize,
+    // No entry below `start` is left to return, and no entry at or above `end`.
+    start: usize,
+    end: usize,
+}
 
 impl<T> Default for SparseComponentStorage<T> {
     fn default() -> SparseComponentStorage<T> {
@@ -20,6 +40,10 @@ impl<T> Default for SparseComponentStorage<T> {
     }
 }
 
+impl<T> TickedStorage for SparseComponentStorage<T> {
+    type Ticks = SparseComponentStorage<ComponentTicks>;
+}
+
 impl<'a, T: 'static + Send + Sync + Clone> ComponentStorage<'a> for SparseComponentStorage<T> {
     type Component = T;
     type Scan = SparseComponentScanner<'a, T>;
@@ -42,14 +66,31 @@ impl<'a, T: 'static + Send + Sync + Clone> ComponentStorage<'a> for SparseCompon
     }
 
     fn scan(&'a self) -> Self::Scan {
-        SparseComponentScanner {
-            map: &self.0,
-            iter: self.0.range(..),
-        }
+        self.scan_range(0..usize::MAX)
     }
 
     fn scan_mut(&'a mut self) -> Self::ScanMut {
-        SparseComponentScannerMut(self.0.iter_mut())
+        self.scan_mut_range(0..usize::MAX)
+    }
+
+    fn scan_range(&'a self, range: Range<usize>) -> Self::Scan {
+        SparseComponentScanner::new(&self.0, range)
+    }
+
+    fn scan_mut_range(&'a mut self, range: Range<usize>) -> Self::ScanMut {
+        let start = cmp::min(range.start, range.end);
+        let end = bounded_end(&self.0, range.end);
+        SparseComponentScannerMut {
+            iter_len: self.0.len(),
+            iter: Some(self.0.range_mut(start..range.end)),
+            split: Vec::new(),
+            start,
+            end,
+        }
+    }
+
+    fn estimated_len(&self) -> usize {
+        self.0.len()
     }
 }
 
@@ -59,23 +100,58 @@ impl<T: 'static> SparseComponentStorage<T> {
     }
 }
 
+// The end of a range of indexes, bounded by the last entry in the map.
+fn bounded_end<T>(map: &BTreeMap<usize, T>, end: usize) -> usize {
+    match map.last_key_value() {
+        Some((&last, _)) => cmp::min(end, last + 1),
+        None => 0,
+    }
+}
+
+impl<'a, T> SparseComponentScanner<'a, T> {
+    fn new(map: &'a BTreeMap<usize, T>, range: Range<usize>) -> SparseComponentScanner<'a, T> {
+        let start = cmp::min(range.start, range.end);
+        SparseComponentScanner {
+            map,
+            iter: map.range(start..range.end),
+            start,
+            end: range.end,
+        }
+    }
+}
+
 impl<'a, T> ComponentScanner for SparseComponentScanner<'a, T> {
     type Item = &'a T;
 
+    // Returns every entry at most once, in index order, and never an entry outside of the range
+    // the scan started with.
     fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
         let until = until.unwrap_or(0);
 
-        if let Some((id, v)) = self.iter.next() {
-            if *id >= until {
-                return Some((v, *id));
+        let next = match self.iter.next() {
+            Some((id, v)) if *id >= until => Some((v, *id)),
+            Some(_) if until < self.end => {
+                self.iter = self.map.range(until..self.end);
+                self.iter.next().map(|(id, v)| (v, *id))
             }
-            self.iter = self.map.range(until..);
-            if let Some((id, v)) = self.iter.next() {
-                return Some((v, *id));
+            _ => None,
+        };
+        match next {
+            Some((_, index)) => self.start = index + 1,
+            None => {
+                self.start = self.end;
+                self.iter = self.map.range(self.end..self.end);
             }
         }
+        next
+    }
 
-        None
+    // The number of entries in the map is only an upper bound when the scan is ranged or partly
+    // consumed, so it is also bounded by the number of indexes left in the range, up to the last
+    // entry.
+    fn estimated_len(&self) -> Option<usize> {
+        let end = bounded_end(self.map, self.end);
+        Some(cmp::min(self.map.len(), end.saturating_sub(self.start)))
     }
 }
 
@@ -83,13 +159,98 @@ impl<'a, T> ComponentScanner for SparseComponentScannerMut<'a, T> {
     type Item = &'a mut T;
 
     fn scan(&mut self, until: Option<usize>) -> Option<(Self::Item, usize)> {
-        // This is very slow, mutably scanning over a sparse component will always unnecessarily
-        // scan through the entries one by one
-        while let Some((id, v)) = self.0.next() {
-            if until.is_none() || *id >= until.unwrap() {
-                return Some((v, *id));
+        let until = cmp::max(until.unwrap_or(0), self.start);
+
+        while until < self.end {
+            let next = match self.iter.as_mut().and_then(|iter| iter.next()) {
+                Some((&index, component)) => {
+                    self.iter_len = self.iter_len.saturating_sub(1);
+                    Some((index, component))
+                }
+                None => self.split.pop(),
+            };
+            match next {
+                Some((index, component)) if index >= until => {
+                    self.start = index + 1;
+                    return Some((component, index));
+                }
+                Some(_) => {}
+                None => break,
             }
         }
+
+        self.start = self.end;
+        self.iter = None;
+        self.split.clear();
         None
     }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(cmp::min(
+            self.iter_len + self.split.len(),
+            self.end.saturating_sub(self.start),
+        ))
+    }
+}
+
+impl<'a, T> SplitScanner for SparseComponentScanner<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        let mut iter = self.iter.clone();
+        match (iter.next(), iter.next_back()) {
+            (Some((&first, _)), Some((&last, _))) => first..last + 1,
+            (Some((&first, _)), None) => first..first + 1,
+            _ => 0..0,
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let range = self.index_range();
+        let mid = cmp::max(range.start, cmp::min(index, range.end));
+        (
+            SparseComponentScanner::new(self.map, range.start..mid),
+            SparseComponentScanner::new(self.map, mid..range.end),
+        )
+    }
+}
+
+impl<'a, T> SplitScanner for SparseComponentScannerMut<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        if self.start < self.end {
+            self.start..self.end
+        } else {
+            0..0
+        }
+    }
+
+    // Only moves the entries at or above the split point, so splitting a scan into parts from the
+    // end, as `ParallelScan` does, moves each entry at most once.
+    fn split_at(mut self, index: usize) -> (Self, Self) {
+        let mid = cmp::max(self.start, cmp::min(index, self.end));
+
+        let above = self.split.partition_point(|&(i, _)| i >= mid);
+        let mut upper = self.split.drain(..above).collect::<Vec<_>>();
+        if self.split.is_empty() {
+            if let Some(iter) = self.iter.as_mut() {
+                while let Some((&i, component)) = iter.next_back() {
+                    self.iter_len = self.iter_len.saturating_sub(1);
+                    if i >= mid {
+                        upper.push((i, component));
+                    } else {
+                        self.split.push((i, component));
+                        break;
+                    }
+                }
+            }
+        }
+
+        let upper = SparseComponentScannerMut {
+            iter: None,
+            split: upper,
+            iter_len: 0,
+            start: mid,
+            end: self.end,
+        };
+        self.end = mid;
+        (self, upper)
+    }
 }
@@ -0,0 +1,371 @@
+use std::cmp;
+use std::mem;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+use change_tick::{ComponentTicks, TickedStorage};
+use component::ComponentStorage;
+use component_scanner::ComponentScanner;
+use parallel_scan::SplitScanner;
+
+/// Stores components contiguously in a packed array, with a sparse array mapping entity indexes to
+/// positions in the packed array.  Inserting and removing are O(1), and removing swaps the last
+/// component into the removed position.
+///
+/// Scanning must happen in index order, so as long as the packed components are in index order,
+/// scanning iterates directly over the packed array.  Once inserts and removals have put them out
+/// of order, scans follow the packed positions sorted by index instead, which are computed on the
+/// first scan after a change and shared by every scan until the next one.  `sort` puts the packed
+/// components back in index order, and mutable scans sort them first, since they hold exclusive
+/// access anyway.
+///
+/// The storage keeps track of which packed components are still in index order, so ordering them
+/// only sorts the `k` components that were inserted out of order or moved by a removal since the
+/// last sort and merges them with the rest, which is O(n + k log k) rather than a full sort.
+#[derive(Clone)]
+pub struct SparseSetComponentStorage<T> {
+    sparse: Vec<Option<usize>>,
+    indexes: Vec<usize>,
+    packed: Vec<T>,
+    // Every packed component below `sorted_len` is in index order, except at the `dirty` positions,
+    // which removals have filled with other components.  The positions in `dirty` may repeat, and
+    // may be past `sorted_len` after later removals, in which case they are ignored.
+    sorted_len: usize,
+    dirty: Vec<usize>,
+    order: OnceLock<Vec<usize>>,
+}
+
+pub struct SparseSetComponentScanner<'a, T: 'a> {
+    next: usize,
+    end: usize,
+    indexes: &'a [usize],
+    // The packed positions in index order, or None if the packed components are in index order.
+    order: Option<&'a [usize]>,
+    packed: &'a [T],
+}
+
+pub struct SparseSetComponentScannerMut<'a, T: 'a> {
+    indexes: &'a [usize],
+    packed: &'a mut [T],
+}
+
+impl<T> Default for SparseSetComponentStorage<T> {
+    fn default() -> SparseSetComponentStorage<T> {
+        SparseSetComponentStorage {
+            sparse: Vec::new(),
+            indexes: Vec::new(),
+            packed: Vec::new(),
+            sorted_len: 0,
+            dirty: Vec::new(),
+            order: OnceLock::new(),
+        }
+    }
+}
+
+impl<T> TickedStorage for SparseSetComponentStorage<T> {
+    type Ticks = SparseSetComponentStorage<ComponentTicks>;
+}
+
+impl<'a, T: 'static + Send + Sync + Clone> ComponentStorage<'a> for SparseSetComponentStorage<T> {
+    type Component = T;
+    type Scan = SparseSetComponentScanner<'a, T>;
+    type ScanMut = SparseSetComponentScannerMut<'a, T>;
+
+    fn get(&self, index: usize) -> Option<&T> {
+        match self.sparse.get(index) {
+            Some(&Some(slot)) => Some(&self.packed[slot]),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        match self.sparse.get(index) {
+            Some(&Some(slot)) => Some(&mut self.packed[slot]),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, index: usize, component: T) -> Option<T> {
+        while index >= self.sparse.len() {
+            self.sparse.push(None);
+        }
+
+        if let Some(slot) = self.sparse[index] {
+            return Some(mem::replace(&mut self.packed[slot], component));
+        }
+
+        let in_order = match self.indexes.last() {
+            Some(&last) => last < index,
+            None => true,
+        };
+        if in_order && self.is_sorted() {
+            self.sorted_len += 1;
+        }
+        self.order.take();
+
+        self.sparse[index] = Some(self.packed.len());
+        self.indexes.push(index);
+        self.packed.push(component);
+        None
+    }
+
+    fn remove(&mut self, index: usize) -> Option<T> {
+        let slot = self.sparse.get_mut(index)?.take()?;
+        let component = self.packed.swap_remove(slot);
+        self.indexes.swap_remove(slot);
+        if slot < self.indexes.len() {
+            self.sparse[self.indexes[slot]] = Some(slot);
+            if slot < self.sorted_len {
+                self.dirty.push(slot);
+            }
+        }
+        self.sorted_len = cmp::min(self.sorted_len, self.indexes.len());
+        self.order.take();
+        Some(component)
+    }
+
+    fn scan(&'a self) -> Self::Scan {
+        self.scan_range(0..usize::MAX)
+    }
+
+    fn scan_mut(&'a mut self) -> Self::ScanMut {
+        self.scan_mut_range(0..usize::MAX)
+    }
+
+    fn scan_range(&'a self, range: Range<usize>) -> Self::Scan {
+        let order = self.order();
+        SparseSetComponentScanner {
+            next: rank(&self.indexes, order, range.start),
+            end: range.end,
+            indexes: &self.indexes,
+            order,
+            packed: &self.packed,
+        }
+    }
+
+    fn scan_mut_range(&'a mut self, range: Range<usize>) -> Self::ScanMut {
+        self.sort();
+        let start = rank(&self.indexes, None, range.start);
+        let end = cmp::max(start, rank(&self.indexes, None, range.end));
+        SparseSetComponentScannerMut {
+            indexes: &self.indexes[start..end],
+            packed: &mut self.packed[start..end],
+        }
+    }
+
+    fn estimated_len(&self) -> usize {
+        self.packed.len()
+    }
+}
+
+impl<T: 'static> SparseSetComponentStorage<T> {
+    pub fn new() -> SparseSetComponentStorage<T> {
+        SparseSetComponentStorage::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.packed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packed.is_empty()
+    }
+
+    /// Sorts the packed components into index order if inserts and removals have put them out of
+    /// order, so that scans can iterate over the packed components directly.
+    pub fn sort(&mut self) {
+        if self.is_sorted() {
+            return;
+        }
+
+        // Moves every packed component into place by following the cycles of the permutation,
+        // marking each position as done by pointing it at itself.
+        let mut order = self.order.take().unwrap_or_else(|| self.sorted_order());
+        for start in 0..order.len() {
+            let mut slot = start;
+            loop {
+                let from = order[slot];
+                order[slot] = slot;
+                if from == start {
+                    break;
+                }
+                self.packed.swap(slot, from);
+                self.indexes.swap(slot, from);
+                slot = from;
+            }
+        }
+
+        for (slot, &index) in self.indexes.iter().enumerate() {
+            self.sparse[index] = Some(slot);
+        }
+        self.sorted_len = self.indexes.len();
+        self.dirty.clear();
+    }
+
+    fn is_sorted(&self) -> bool {
+        self.sorted_len == self.indexes.len() && self.dirty.is_empty()
+    }
+
+    // The packed positions in index order, or None if the packed components are in index order.
+    fn order(&self) -> Option<&[usize]> {
+        if self.is_sorted() {
+            None
+        } else {
+            Some(self.order.get_or_init(|| self.sorted_order()))
+        }
+    }
+
+    // Sorts only the positions that are out of order, then merges them with the positions below
+    // `sorted_len` that are still in order.
+    fn sorted_order(&self) -> Vec<usize> {
+        let indexes = &self.indexes;
+        let mut dirty = self
+            .dirty
+            .iter()
+            .cloned()
+            .filter(|&slot| slot < self.sorted_len)
+            .collect::<Vec<_>>();
+        dirty.sort_unstable();
+        dirty.dedup();
+
+        let mut unsorted = dirty.clone();
+        unsorted.extend(self.sorted_len..indexes.len());
+        unsorted.sort_unstable_by_key(|&slot| indexes[slot]);
+
+        let mut order = Vec::with_capacity(indexes.len());
+        let mut dirty = dirty.into_iter().peekable();
+        let mut unsorted = unsorted.into_iter().peekable();
+        for slot in 0..self.sorted_len {
+            if dirty.next_if_eq(&slot).is_some() {
+                continue;
+            }
+            while let Some(next) = unsorted.next_if(|&u| indexes[u] < indexes[slot]) {
+                order.push(next);
+            }
+            order.push(slot);
+        }
+        order.extend(unsorted);
+        order
+    }
+}
+
+// The number of components with an index below the given index.
+fn rank(indexes: &[usize], order: Option<&[usize]>, index: usize) -> usize {
+    match order {
+        Some(order) => order.partition_point(|&slot| indexes[slot] < index),
+        None => indexes.partition_point(|&i| i < index),
+    }
+}
+
+impl<'a, T> SparseSetComponentScanner<'a, T> {
+    // The packed position and index of the next component.
+    fn peek(&self) -> Option<(usize, usize)> {
+        let slot = match self.order {
+            Some(order) => *order.get(self.next)?,
+            None if self.next < self.indexes.len() => self.next,
+            None => return None,
+        };
+        Some((slot, self.indexes[slot]))
+    }
+}
+
+impl<'a, T> ComponentScanner for SparseSetComponentScanner<'a, T> {
+    type Item = &'a T;
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(&'a T, usize)> {
+        let (mut slot, mut index) = self.peek()?;
+        if let Some(until) = until {
+            if index < until {
+                self.next = rank(self.indexes, self.order, until);
+                let next = self.peek()?;
+                slot = next.0;
+                index = next.1;
+            }
+        }
+
+        if index < self.end {
+            self.next += 1;
+            Some((&self.packed[slot], index))
+        } else {
+            None
+        }
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(rank(self.indexes, self.order, self.end).saturating_sub(self.next))
+    }
+}
+
+impl<'a, T> ComponentScanner for SparseSetComponentScannerMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn scan(&mut self, until: Option<usize>) -> Option<(&'a mut T, usize)> {
+        let skip = self.indexes.partition_point(|&i| i < until.unwrap_or(0));
+        if skip >= self.indexes.len() {
+            self.indexes = &[];
+            self.packed = &mut [];
+            return None;
+        }
+
+        let (first, rest) = mem::take(&mut self.packed)[skip..].split_first_mut()?;
+        let index = self.indexes[skip];
+        self.indexes = &self.indexes[skip + 1..];
+        self.packed = rest;
+        Some((first, index))
+    }
+
+    fn estimated_len(&self) -> Option<usize> {
+        Some(self.indexes.len())
+    }
+}
+
+impl<'a, T> SplitScanner for SparseSetComponentScanner<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        let last = match self.order {
+            Some(order) => order.last().map(|&slot| self.indexes[slot]),
+            None => self.indexes.last().cloned(),
+        };
+        match (self.peek(), last) {
+            (Some((_, first)), Some(last)) if first < self.end => {
+                first..cmp::min(last + 1, self.end)
+            }
+            _ => 0..0,
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = cmp::max(self.next, rank(self.indexes, self.order, index));
+        let lower = SparseSetComponentScanner {
+            next: self.next,
+            end: cmp::min(index, self.end),
+            ..self
+        };
+        let upper = SparseSetComponentScanner { next: mid, ..self };
+        (lower, upper)
+    }
+}
+
+impl<'a, T> SplitScanner for SparseSetComponentScannerMut<'a, T> {
+    fn index_range(&self) -> Range<usize> {
+        match (self.indexes.first(), self.indexes.last()) {
+            (Some(&first), Some(&last)) => first..last + 1,
+            _ => 0..0,
+        }
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.indexes.partition_point(|&i| i < index);
+        let (lower_indexes, upper_indexes) = self.indexes.split_at(mid);
+        let (lower_packed, upper_packed) = self.packed.split_at_mut(mid);
+        (
+            SparseSetComponentScannerMut {
+                indexes: lower_indexes,
+                packed: lower_packed,
+            },
+            SparseSetComponentScannerMut {
+                indexes: upper_indexes,
+                packed: upper_packed,
+            },
+        )
+    }
+}
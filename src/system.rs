@@ -0,0 +1,199 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use failure::Error;
+
+use world::World;
+use world_multi_lock::{LockAccess, LockId, WorldMultiLocker};
+
+/// A unit of work run against a `World` by a `Dispatcher`.  Every resource and component a system
+/// uses is declared up front in `Data` as a tuple of `WorldMultiLocker` lockers, which is how the
+/// `Dispatcher` knows which systems may run at the same time.
+pub trait System<'a>: Send {
+    type Data: Default + WorldMultiLocker<'a>;
+
+    fn run(&mut self, data: <Self::Data as WorldMultiLocker<'a>>::Handles);
+}
+
+/// Runs a set of systems against a `World`, running systems whose locks do not conflict in
+/// parallel.  Two systems conflict if they lock the same resource or component and at least one of
+/// them locks it for writing, and conflicting systems always run in the order they were added.
+///
+/// The calling thread runs systems alongside up to `thread_count - 1` worker threads.  Worker
+/// threads are scoped to a single call to `dispatch`, so that the systems can borrow the `World`
+/// directly, which means every `dispatch` spawns and joins them again.  A Dispatcher with a thread
+/// count of 1 runs every system on the calling thread without spawning any threads.
+pub struct Dispatcher {
+    systems: Vec<SystemEntry>,
+    thread_count: usize,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dispatcher {
+    /// Creates a Dispatcher that uses as many threads as there is available parallelism.
+    pub fn new() -> Dispatcher {
+        Dispatcher::with_thread_count(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+
+    pub fn with_thread_count(thread_count: usize) -> Dispatcher {
+        Dispatcher {
+            systems: Vec::new(),
+            thread_count: thread_count.max(1),
+        }
+    }
+
+    pub fn add_system<S>(&mut self, system: S)
+    where
+        S: 'static + for<'a> System<'a>,
+    {
+        let accesses = system_accesses::<S>();
+        let dependencies = self
+            .systems
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| conflicts(&entry.accesses, &accesses))
+            .map(|(i, _)| i)
+            .collect();
+
+        self.systems.push(SystemEntry {
+            system: Box::new(system),
+            accesses,
+            dependencies,
+        });
+    }
+
+    /// Runs every system once.  If any systems fail to lock their data, the remaining systems are
+    /// still run and the first error is returned.  If any systems panic, the remaining systems are
+    /// still run and then the first panic is resumed.
+    pub fn dispatch(&mut self, world: &World) -> Result<(), Error> {
+        let count = self.systems.len();
+        let thread_count = self.thread_count.min(count);
+        let mut dependents = vec![Vec::new(); count];
+        let mut remaining = Vec::with_capacity(count);
+        for (i, entry) in self.systems.iter().enumerate() {
+            for &dependency in &entry.dependencies {
+                dependents[dependency].push(i);
+            }
+            remaining.push(entry.dependencies.len());
+        }
+
+        let state = Mutex::new(DispatchState {
+            ready: (0..count).filter(|&i| remaining[i] == 0).rev().collect(),
+            remaining,
+            finished: 0,
+            error: None,
+            panic: None,
+        });
+        let ready_signal = Condvar::new();
+        let systems = self
+            .systems
+            .iter_mut()
+            .map(|entry| Mutex::new(&mut entry.system))
+            .collect::<Vec<_>>();
+
+        let worker = || loop {
+            let index = {
+                let mut state = state.lock().unwrap();
+                loop {
+                    if let Some(index) = state.ready.pop() {
+                        break index;
+                    } else if state.finished == count {
+                        return;
+                    }
+                    state = ready_signal.wait(state).unwrap();
+                }
+            };
+
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                systems[index].lock().unwrap().run_system(world)
+            }));
+
+            let mut state = state.lock().unwrap();
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    state.error.get_or_insert(err);
+                }
+                Err(panic) => {
+                    state.panic.get_or_insert(panic);
+                }
+            }
+            state.finished += 1;
+            for &dependent in &dependents[index] {
+                state.remaining[dependent] -= 1;
+                if state.remaining[dependent] == 0 {
+                    state.ready.push(dependent);
+                }
+            }
+            ready_signal.notify_all();
+        };
+
+        thread::scope(|scope| {
+            for _ in 1..thread_count {
+                scope.spawn(worker);
+            }
+            worker();
+        });
+
+        let state = state.into_inner().unwrap();
+        if let Some(panic) = state.panic {
+            panic::resume_unwind(panic);
+        }
+        match state.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+struct SystemEntry {
+    system: Box<dyn RunSystem>,
+    accesses: Vec<(LockId, LockAccess)>,
+    dependencies: Vec<usize>,
+}
+
+struct DispatchState {
+    ready: Vec<usize>,
+    remaining: Vec<usize>,
+    finished: usize,
+    error: Option<Error>,
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+trait RunSystem: Send {
+    fn run_system(&mut self, world: &World) -> Result<(), Error>;
+}
+
+impl<S> RunSystem for S
+where
+    S: for<'a> System<'a>,
+{
+    fn run_system(&mut self, world: &World) -> Result<(), Error> {
+        run_system(self, world)
+    }
+}
+
+fn run_system<'a, S: System<'a>>(system: &mut S, world: &'a World) -> Result<(), Error> {
+    let data = world.multi_lock::<S::Data>()?;
+    system.run(data);
+    Ok(())
+}
+
+fn system_accesses<'a, S: System<'a>>() -> Vec<(LockId, LockAccess)> {
+    S::Data::default().accesses()
+}
+
+fn conflicts(a: &[(LockId, LockAccess)], b: &[(LockId, LockAccess)]) -> bool {
+    a.iter().any(|&(id_a, access_a)| {
+        b.iter().any(|&(id_b, access_b)| {
+            id_a == id_b && (access_a == LockAccess::Write || access_b == LockAccess::Write)
+        })
+    })
+}
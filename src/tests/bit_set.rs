@@ -0,0 +1,35 @@
+use bit_set::*;
+
+#[test]
+fn test_bit_set() {
+    let mut set = HierarchicalBitSet::new();
+    let values = [0, 1, 63, 64, 65, 4095, 4096, 262_143, 262_144, 1_000_000];
+    for &v in values.iter() {
+        assert!(set.insert(v));
+    }
+    assert!(!set.insert(64));
+    assert_eq!(set.len(), values.len());
+
+    let mut found = Vec::new();
+    let mut next = set.next(0);
+    while let Some(v) = next {
+        found.push(v);
+        next = set.next(v + 1);
+    }
+    assert_eq!(found, values.to_vec());
+
+    assert_eq!(set.next(66), Some(4095));
+    assert_eq!(set.next(262_145), Some(1_000_000));
+    assert_eq!(set.next(1_000_001), None);
+
+    assert!(set.remove(4095));
+    assert!(set.remove(4096));
+    assert!(!set.remove(4096));
+    assert!(!set.contains(4096));
+    assert_eq!(set.next(66), Some(262_143));
+
+    assert!(set.remove(262_143));
+    assert!(set.remove(262_144));
+    assert_eq!(set.next(66), Some(1_000_000));
+    assert_eq!(set.len(), values.len() - 4);
+}
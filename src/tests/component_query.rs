@@ -1,7 +1,16 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use component::*;
 use component_scanner::*;
 use dense_component::*;
+use error::Error;
+use flag_component::*;
+use parallel_scan::SplitScanner;
 use sparse_component::*;
+use sparse_set_component::*;
 
 #[test]
 fn test_join() {
@@ -26,17 +35,19 @@ fn test_join() {
         compb.insert(9, 9);
 
         let values = component_scan_join((compa.scan(), compb.scan()))
+            .unwrap()
             .iter()
             .collect::<Vec<_>>();
         assert_eq!(values, vec![(&2, &2), (&6, &6), (&9, &9)]);
 
-        let mut value_scan = component_scan_join((compa.scan(), compb.scan()));
+        let mut value_scan = component_scan_join((compa.scan(), compb.scan())).unwrap();
         assert_eq!(value_scan.scan(None), Some(((&2, &2), 2)));
         assert_eq!(value_scan.scan(Some(7)), Some(((&9, &9), 9)));
     }
 
     go::<SparseComponentStorage<i32>>();
     go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
 }
 
 #[test]
@@ -51,7 +62,7 @@ fn test_opt() {
         comp.insert(5, 5);
         comp.insert(6, 6);
 
-        let values = comp.scan().opt().iter().take(8).collect::<Vec<_>>();
+        let values = comp.scan().opt_until(8).iter().collect::<Vec<_>>();
         assert_eq!(
             values,
             vec![
@@ -66,15 +77,17 @@ fn test_opt() {
             ]
         );
 
-        let mut value_scan = comp.scan().opt();
+        let mut value_scan = comp.scan().opt_until(8);
         assert_eq!(value_scan.scan(Some(2)), Some((None, 2)));
         assert_eq!(value_scan.scan(Some(5)), Some((Some(&5), 5)));
         assert_eq!(value_scan.scan(None), Some((Some(&6), 6)));
         assert_eq!(value_scan.scan(None), Some((None, 7)));
+        assert_eq!(value_scan.scan(None), None);
     }
 
     go::<SparseComponentStorage<i32>>();
     go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
 }
 
 #[test]
@@ -99,16 +112,22 @@ fn test_limit() {
         compb.insert(6, 6);
         compb.insert(9, 9);
 
-        let values = compa.scan().limit(compb.scan()).iter().collect::<Vec<_>>();
+        let values = compa
+            .scan()
+            .limit(compb.scan())
+            .unwrap()
+            .iter()
+            .collect::<Vec<_>>();
         assert_eq!(values, vec![&2, &6, &9]);
 
-        let mut value_scan = compa.scan().limit(compb.scan());
+        let mut value_scan = compa.scan().limit(compb.scan()).unwrap();
         assert_eq!(value_scan.scan(Some(3)), Some((&6, 6)));
         assert_eq!(value_scan.scan(None), Some((&9, 9)));
     }
 
     go::<SparseComponentStorage<i32>>();
     go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
 }
 
 #[test]
@@ -144,6 +163,7 @@ fn test_not() {
 
     go::<SparseComponentStorage<i32>>();
     go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
 }
 
 // Make sure that Box<ComponentScanner> works as expected and can be used for dynamically
@@ -168,7 +188,7 @@ fn test_dynamic() {
         compb.insert(4, 4);
         compb.insert(6, 6);
 
-        let mut value_scan: Box<ComponentScanner<Item = _>> = Box::new(compa.scan());
+        let mut value_scan: Box<dyn ComponentScanner<Item = _>> = Box::new(compa.scan());
         value_scan = Box::new(value_scan.not(compb.scan()));
 
         assert_eq!(value_scan.scan(Some(2)), Some((&3, 3)));
@@ -178,4 +198,657 @@ fn test_dynamic() {
 
     go::<SparseComponentStorage<i32>>();
     go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+}
+
+#[test]
+fn test_sparse_set_removal() {
+    let mut comp = SparseSetComponentStorage::<i32>::new();
+    for i in 0..8 {
+        comp.insert(i, i as i32);
+    }
+    comp.insert(5, 50);
+
+    // Swap removal puts the packed components out of index order
+    assert_eq!(comp.remove(1), Some(1));
+    assert_eq!(comp.remove(4), Some(4));
+    assert_eq!(comp.remove(4), None);
+    assert_eq!(comp.len(), 6);
+    assert_eq!(comp.get(7), Some(&7));
+    assert_eq!(comp.get(5), Some(&50));
+
+    let values = comp.scan().iter().collect::<Vec<_>>();
+    assert_eq!(values, vec![&0, &2, &3, &50, &6, &7]);
+
+    let mut value_scan = comp.scan();
+    assert_eq!(value_scan.scan(Some(4)), Some((&50, 5)));
+    assert_eq!(value_scan.scan(None), Some((&6, 6)));
+
+    for v in comp.scan_mut().iter() {
+        *v += 1;
+    }
+
+    // Sorting the storage puts the packed components back in index order
+    comp.sort();
+    let mut value_scan = comp.scan();
+    assert_eq!(value_scan.scan(Some(1)), Some((&3, 2)));
+    assert_eq!(value_scan.scan(Some(6)), Some((&7, 6)));
+    assert_eq!(value_scan.scan(None), Some((&8, 7)));
+    assert_eq!(value_scan.scan(None), None);
+}
+
+#[test]
+fn test_sparse_set_churn() {
+    let mut comp = SparseSetComponentStorage::<i32>::new();
+    let mut model = BTreeMap::new();
+    comp.insert(1_000_000, -1);
+    model.insert(1_000_000, -1);
+
+    let mut seed = 1u32;
+    for _ in 0..2000 {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let index = (seed >> 16) as usize % 64;
+        if seed >> 30 == 0 {
+            assert_eq!(comp.remove(index), model.remove(&index));
+        } else {
+            assert_eq!(
+                comp.insert(index, index as i32),
+                model.insert(index, index as i32)
+            );
+        }
+
+        let values = comp.scan().iter().collect::<Vec<_>>();
+        assert_eq!(values, model.values().collect::<Vec<_>>());
+
+        let mut value_scan = comp.scan_range(16..48);
+        let expected = model.range(32..48).next().map(|(&i, v)| (v, i));
+        assert_eq!(value_scan.scan(Some(32)), expected);
+
+        let mut value_scan = comp.scan_mut_range(16..48);
+        let mut indexes = Vec::new();
+        while let Some((v, index)) = value_scan.scan(None) {
+            *v += 1;
+            indexes.push(index);
+        }
+        for (&index, v) in model.range_mut(16..48) {
+            *v += 1;
+            assert_eq!(indexes.remove(0), index);
+        }
+        assert!(indexes.is_empty());
+    }
+
+    // Components moved by removals and inserted below the highest index are merged back into the
+    // components that stayed in order.
+    let mut comp = SparseSetComponentStorage::<i32>::new();
+    for i in 0..8 {
+        comp.insert(i * 2, i as i32);
+    }
+    comp.remove(2);
+    comp.remove(8);
+    comp.insert(9, 9);
+    comp.insert(1, 1);
+    comp.remove(14);
+    comp.insert(20, 20);
+    let expected = vec![
+        (0, 0),
+        (1, 1),
+        (4, 2),
+        (6, 3),
+        (9, 9),
+        (10, 5),
+        (12, 6),
+        (20, 20),
+    ];
+    let scan = |comp: &SparseSetComponentStorage<i32>| {
+        let mut scan = comp.scan();
+        let mut entries = Vec::new();
+        while let Some((&v, index)) = scan.scan(None) {
+            entries.push((index, v));
+        }
+        entries
+    };
+    assert_eq!(scan(&comp), expected);
+    assert_eq!(comp.scan_mut().iter().count(), expected.len());
+    assert_eq!(scan(&comp), expected);
+    for &(index, v) in &expected {
+        assert_eq!(comp.get(index), Some(&v));
+    }
+}
+
+#[test]
+fn test_flag() {
+    #[derive(Clone, Default, PartialEq, Debug)]
+    struct Tag;
+
+    let mut comp = DenseComponentStorage::<i32>::new();
+    let mut tags = FlagComponentStorage::<Tag>::new();
+
+    for i in 0..10 {
+        comp.insert(i, i as i32);
+    }
+    tags.insert(2, Tag);
+    tags.insert(5, Tag);
+    tags.insert(9, Tag);
+    tags.insert(100_000, Tag);
+    assert_eq!(tags.insert(5, Tag), Some(Tag));
+    assert_eq!(tags.len(), 4);
+
+    let values = comp
+        .scan()
+        .limit(tags.scan())
+        .unwrap()
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![&2, &5, &9]);
+
+    let values = comp.scan().not(tags.scan()).iter().collect::<Vec<_>>();
+    assert_eq!(values, vec![&0, &1, &3, &4, &6, &7, &8]);
+
+    let mut tag_scan = tags.scan_mut();
+    let (first, _) = tag_scan.scan(Some(6)).unwrap();
+    let (second, index) = tag_scan.scan(None).unwrap();
+    assert_eq!((&*first, &*second, index), (&Tag, &Tag, 100_000));
+    assert_eq!(tag_scan.scan(None), None);
+
+    assert_eq!(tags.remove(5), Some(Tag));
+    assert_eq!(tags.remove(5), None);
+    assert_eq!(tags.get(5), None);
+    assert_eq!(tags.get(9), Some(&Tag));
+}
+
+#[test]
+fn test_par_scan() {
+    fn go<S>()
+    where
+        S: for<'a> ComponentStorage<'a, Component = i32>,
+    {
+        let mut positions = S::default();
+        let mut velocities = S::default();
+        for i in 0..10_000 {
+            positions.insert(i, 0);
+            if i % 3 != 0 {
+                velocities.insert(i, i as i32);
+            }
+        }
+        // Leaves sparse set storages out of index order
+        positions.remove(0);
+        positions.insert(0, 0);
+        velocities.remove(1);
+        velocities.insert(1, 1);
+
+        component_scan_join((positions.scan_mut(), velocities.scan()))
+            .unwrap()
+            .par_scan()
+            .thread_count(4)
+            .batch_size(100)
+            .for_each(|(p, v), index| {
+                assert_eq!(*v as usize, index);
+                *p += *v;
+            });
+        component_scan_join((positions.scan_mut(), velocities.scan()))
+            .unwrap()
+            .par_for_each(|(p, v)| *p += *v);
+
+        let index_sum = AtomicUsize::new(0);
+        positions
+            .scan()
+            .par_scan()
+            .thread_count(3)
+            .for_each(|p, index| {
+                assert_eq!(*p as usize, if index % 3 == 0 { 0 } else { 2 * index });
+                index_sum.fetch_add(index, Ordering::Relaxed);
+            });
+        assert_eq!(index_sum.into_inner(), (0..10_000).sum());
+
+        let index_sum = AtomicUsize::new(0);
+        positions
+            .scan()
+            .not(velocities.scan())
+            .par_scan()
+            .thread_count(3)
+            .batch_size(64)
+            .for_each(|p, index| {
+                assert_eq!(*p, 0);
+                index_sum.fetch_add(index, Ordering::Relaxed);
+            });
+        assert_eq!(index_sum.into_inner(), (0..10_000).step_by(3).sum());
+    }
+
+    go::<SparseComponentStorage<i32>>();
+    go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+}
+
+#[test]
+fn test_range() {
+    fn go<S>()
+    where
+        S: for<'a> ComponentStorage<'a, Component = i32>,
+    {
+        let mut comp = S::default();
+        for &i in &[9, 1, 2, 3, 5, 6, 7, 12] {
+            comp.insert(i, i as i32);
+        }
+        comp.remove(1);
+
+        let indexes = |scan: &mut dyn ComponentScanner<Item = &i32>| {
+            let mut indexes = Vec::new();
+            while let Some((_, index)) = scan.scan(None) {
+                indexes.push(index);
+            }
+            indexes
+        };
+
+        assert_eq!(indexes(&mut comp.scan_range(3..9)), vec![3, 5, 6, 7]);
+        assert_eq!(indexes(&mut comp.scan_range(0..3)), vec![2]);
+        assert_eq!(indexes(&mut comp.scan_range(8..100)), vec![9, 12]);
+        assert_eq!(indexes(&mut comp.scan_range(13..100)), vec![]);
+        assert_eq!(
+            indexes(&mut comp.scan_range(Range { start: 6, end: 4 })),
+            vec![]
+        );
+        assert_eq!(indexes(&mut comp.scan().range(4..10)), vec![5, 6, 7, 9]);
+
+        {
+            let mut scan = comp.scan_range(2..10);
+            assert_eq!(scan.scan(Some(6)), Some((&6, 6)));
+            assert_eq!(scan.scan(Some(8)), Some((&9, 9)));
+            assert_eq!(scan.scan(None), None);
+
+            let mut scan = comp.scan().range(2..10);
+            assert_eq!(scan.scan(Some(6)), Some((&6, 6)));
+            assert_eq!(scan.scan(Some(10)), None);
+            assert_eq!(scan.scan(None), None);
+        }
+
+        for c in comp.scan_mut_range(5..10).iter() {
+            *c *= 10;
+        }
+        {
+            let mut scan = comp.scan_mut_range(3..60);
+            assert_eq!(scan.scan(Some(4)), Some((&mut 50, 5)));
+            assert_eq!(scan.scan(Some(9)), Some((&mut 90, 9)));
+            assert_eq!(scan.scan(None), Some((&mut 12, 12)));
+            assert_eq!(scan.scan(None), None);
+        }
+        assert_eq!(comp.scan_mut_range(13..20).scan(None), None);
+        assert_eq!(
+            comp.scan_mut_range(Range { start: 7, end: 3 }).scan(None),
+            None
+        );
+
+        let values = comp.scan().iter().cloned().collect::<Vec<_>>();
+        assert_eq!(values, vec![2, 3, 50, 60, 70, 90, 12]);
+    }
+
+    go::<SparseComponentStorage<i32>>();
+    go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+
+    #[derive(Clone, Default, PartialEq, Debug)]
+    struct Tag;
+
+    let mut tags = FlagComponentStorage::<Tag>::new();
+    for &i in &[2, 5, 9, 100_000] {
+        tags.insert(i, Tag);
+    }
+    let indexes = |scan: FlagComponentScanner<Tag>| scan.map(|_| ()).iter().count();
+    assert_eq!(indexes(tags.scan_range(3..10)), 2);
+    assert_eq!(tags.scan_range(3..10).estimated_len(), Some(4));
+    assert_eq!(tags.scan_mut_range(3..5).estimated_len(), Some(2));
+    assert_eq!(indexes(tags.scan_range(10..100_000)), 0);
+    assert_eq!(
+        tags.scan_mut_range(10..100_001).scan(None),
+        Some((&mut Tag, 100_000))
+    );
+}
+
+#[test]
+fn test_union() {
+    fn go<S>()
+    where
+        S: for<'a> ComponentStorage<'a, Component = i32>,
+    {
+        let mut compa = S::default();
+        let mut compb = S::default();
+        let mut compc = S::default();
+
+        for &i in &[1, 3, 5, 6] {
+            compa.insert(i, i as i32);
+        }
+        for &i in &[2, 3, 6, 9] {
+            compb.insert(i, i as i32 * 10);
+        }
+        for &i in &[3, 4, 6, 7, 8] {
+            compc.insert(i, i as i32 * 100);
+        }
+
+        let values = compa.scan().or(compb.scan()).iter().collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                (Some(&1), None),
+                (None, Some(&20)),
+                (Some(&3), Some(&30)),
+                (Some(&5), None),
+                (Some(&6), Some(&60)),
+                (None, Some(&90)),
+            ]
+        );
+
+        let mut value_scan = component_scan_union((compa.scan(), compb.scan(), compc.scan()));
+        assert_eq!(value_scan.scan(None), Some(((Some(&1), None, None), 1)));
+        assert_eq!(
+            value_scan.scan(Some(3)),
+            Some(((Some(&3), Some(&30), Some(&300)), 3))
+        );
+        assert_eq!(value_scan.scan(None), Some(((None, None, Some(&400)), 4)));
+        assert_eq!(
+            value_scan.scan(Some(7)),
+            Some(((None, None, Some(&700)), 7))
+        );
+        assert_eq!(value_scan.scan(Some(9)), Some(((None, Some(&90), None), 9)));
+        assert_eq!(value_scan.scan(None), None);
+
+        let values = component_scan_join((compc.scan(), compa.scan().or(compb.scan())))
+            .unwrap()
+            .iter()
+            .map(|(c, _)| *c)
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![300, 600]);
+
+        let values = compa
+            .scan()
+            .or(compb.scan())
+            .limit(compc.scan())
+            .unwrap()
+            .not(compa.scan())
+            .iter()
+            .collect::<Vec<_>>();
+        assert!(values.is_empty());
+
+        let values = compc
+            .scan()
+            .not(compa.scan().or(compb.scan()))
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![&400, &700, &800]);
+    }
+
+    go::<SparseComponentStorage<i32>>();
+    go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+}
+
+#[test]
+fn test_join_order() {
+    struct CountScans<'a, S>(S, &'a Cell<usize>);
+
+    impl<'a, S: ComponentScanner> ComponentScanner for CountScans<'a, S> {
+        type Item = S::Item;
+
+        fn scan(&mut self, until: Option<usize>) -> Option<(S::Item, usize)> {
+            self.1.set(self.1.get() + 1);
+            self.0.scan(until)
+        }
+
+        fn estimated_len(&self) -> Option<usize> {
+            self.0.estimated_len()
+        }
+    }
+
+    let mut big = DenseComponentStorage::<i32>::new();
+    let mut small = SparseComponentStorage::<i32>::new();
+    for i in 0..1000 {
+        big.insert(i, i as i32);
+    }
+    for &i in &[10, 500, 999] {
+        small.insert(i, i as i32 * 10);
+    }
+
+    assert_eq!(big.estimated_len(), 1000);
+    assert_eq!(small.estimated_len(), 3);
+    assert_eq!(big.scan().estimated_len(), Some(1000));
+    assert_eq!(big.scan_range(100..200).estimated_len(), Some(100));
+    assert_eq!(big.scan().range(0..10).estimated_len(), Some(10));
+    assert_eq!(
+        big.scan().limit(small.scan()).unwrap().estimated_len(),
+        Some(3)
+    );
+    assert_eq!(small.scan().or(small.scan()).estimated_len(), Some(6));
+    assert_eq!(big.scan().opt_until(usize::MAX).estimated_len(), None);
+    assert!(big.scan().opt_until(usize::MAX).range(0..10).is_bounded());
+
+    // Ranged and partly consumed scans only estimate the entries they have left.
+    let mut wide = SparseComponentStorage::<i32>::new();
+    let mut wide_set = SparseSetComponentStorage::<i32>::new();
+    for i in 0..1000 {
+        wide.insert(i, i as i32);
+        wide_set.insert(i, i as i32);
+    }
+    assert_eq!(wide.scan_range(100..110).estimated_len(), Some(10));
+    assert_eq!(wide_set.scan_range(100..110).estimated_len(), Some(10));
+    let mut wide_scan = wide.scan();
+    let mut wide_set_scan = wide_set.scan();
+    wide_scan.scan(Some(995));
+    wide_set_scan.scan(Some(995));
+    assert_eq!(wide_scan.estimated_len(), Some(4));
+    assert_eq!(wide_set_scan.estimated_len(), Some(4));
+
+    let big_scans = Cell::new(0);
+    let values = component_scan_join((CountScans(big.scan(), &big_scans), small.scan()))
+        .unwrap()
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(values, vec![(&10, &100), (&500, &5000), (&999, &9990)]);
+    // The small scanner drives, so the big one is only sought once per small entry.
+    assert_eq!(big_scans.get(), 3);
+
+    let values = component_scan_join((small.scan(), big.scan().opt_until(1000)))
+        .unwrap()
+        .iter()
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        vec![(&100, Some(&10)), (&5000, Some(&500)), (&9990, Some(&999))]
+    );
+}
+
+#[test]
+fn test_filter() {
+    fn go<S>()
+    where
+        S: for<'a> ComponentStorage<'a, Component = i32>,
+    {
+        let mut compa = S::default();
+        let mut compb = S::default();
+
+        for i in 0..10 {
+            compa.insert(i, i as i32);
+        }
+        for &i in &[2, 3, 4, 7, 8] {
+            compb.insert(i, i as i32 * 10);
+        }
+
+        let values = compa
+            .scan()
+            .filter(|&&a| a % 2 == 0)
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![&0, &2, &4, &6, &8]);
+
+        let mut value_scan = compa.scan().filter(|&&a| a % 3 == 0);
+        assert_eq!(value_scan.scan(Some(1)), Some((&3, 3)));
+        assert_eq!(value_scan.scan(Some(7)), Some((&9, 9)));
+        assert_eq!(value_scan.scan(None), None);
+
+        let values = component_scan_join((
+            compa.scan().filter(|&&a| a % 2 == 0),
+            compb
+                .scan()
+                .filter_map(|&b| if b > 20 { Some(b / 10) } else { None }),
+        ))
+        .unwrap()
+        .iter()
+        .collect::<Vec<_>>();
+        assert_eq!(values, vec![(&4, 4), (&8, 8)]);
+
+        let values = compa
+            .scan()
+            .not(compb.scan().filter(|&&b| b < 50))
+            .iter()
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![&0, &1, &5, &6, &7, &8, &9]);
+    }
+
+    go::<SparseComponentStorage<i32>>();
+    go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+}
+
+#[test]
+fn test_scan_mut_seek() {
+    fn go<S>()
+    where
+        S: for<'a> ComponentStorage<'a, Component = i32>,
+    {
+        let mut comp = S::default();
+        for i in 0..100 {
+            comp.insert(i * 2, i as i32);
+        }
+
+        {
+            let mut scan = comp.scan_mut();
+            let (a, ia) = scan.scan(Some(10)).unwrap();
+            let (b, ib) = scan.scan(Some(11)).unwrap();
+            let (c, ic) = scan.scan(None).unwrap();
+            assert_eq!((ia, ib, ic), (10, 12, 14));
+            *a += 1000;
+            *b += 1000;
+            *c += 1000;
+            assert!(scan.scan(Some(200)).is_none());
+            assert!(scan.scan(Some(200)).is_none());
+        }
+
+        {
+            let mut scan = comp.scan_mut_range(50..60);
+            assert_eq!(scan.scan(Some(55)), Some((&mut 28, 56)));
+            assert_eq!(scan.scan(Some(59)), None);
+        }
+
+        let values = comp.scan().iter().take(9).cloned().collect::<Vec<_>>();
+        assert_eq!(values, vec![0, 1, 2, 3, 4, 1005, 1006, 1007, 8]);
+    }
+
+    go::<SparseComponentStorage<i32>>();
+    go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+}
+
+#[test]
+fn test_sparse_split_mut() {
+    let mut comp = SparseComponentStorage::default();
+    for i in 0..50 {
+        comp.insert(i * 2, i as i32);
+    }
+
+    let mut scan = comp.scan_mut();
+    assert_eq!(scan.scan(Some(10)), Some((&mut 5, 10)));
+    let (lower, upper) = scan.split_at(40);
+    let (middle, upper) = upper.split_at(70);
+    assert_eq!(lower.index_range(), 11..40);
+    assert_eq!(middle.estimated_len(), Some(15));
+
+    let mut upper = upper;
+    assert_eq!(upper.scan(Some(91)), Some((&mut 46, 92)));
+    for c in lower.iter().chain(middle.iter()).chain(upper.iter()) {
+        *c += 1000;
+    }
+
+    let values = comp.scan().iter().cloned().collect::<Vec<_>>();
+    let expected = (0..50)
+        .map(|i| {
+            if (6..35).contains(&i) || i > 46 {
+                i + 1000
+            } else {
+                i
+            }
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn test_opt_until() {
+    fn go<S>()
+    where
+        S: for<'a> ComponentStorage<'a, Component = i32>,
+    {
+        let mut compa = S::default();
+        let mut compb = S::default();
+        compa.insert(1, 1);
+        compa.insert(3, 3);
+        compb.insert(2, 20);
+
+        let values = compa.scan().opt_until(5).iter().collect::<Vec<_>>();
+        assert_eq!(values, vec![None, Some(&1), None, Some(&3), None]);
+        assert_eq!(compa.scan().opt_until(5).estimated_len(), Some(5));
+
+        assert!(!compa.scan().opt_until(usize::MAX).is_bounded());
+        assert!(compa.scan().opt_until(5).is_bounded());
+        assert!(compa
+            .scan()
+            .opt_until(usize::MAX)
+            .limit(compb.scan())
+            .unwrap()
+            .is_bounded());
+        assert!(!compa
+            .scan()
+            .opt_until(usize::MAX)
+            .or(compb.scan())
+            .is_bounded());
+
+        let values = component_scan_join((
+            compa.scan().opt_until(usize::MAX),
+            compb.scan().opt_until(4),
+        ))
+        .unwrap()
+        .iter()
+        .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                (None, None),
+                (Some(&1), None),
+                (None, Some(&20)),
+                (Some(&3), None),
+            ]
+        );
+    }
+
+    go::<SparseComponentStorage<i32>>();
+    go::<DenseComponentStorage<i32>>();
+    go::<SparseSetComponentStorage<i32>>();
+}
+
+#[test]
+fn test_unbounded_join() {
+    let mut comp = DenseComponentStorage::<i32>::default();
+    comp.insert(1, 1);
+    match component_scan_join((
+        comp.scan().opt_until(usize::MAX),
+        comp.scan().opt_until(usize::MAX),
+    )) {
+        Err(Error::UnboundedJoin) => {}
+        _ => panic!("expected an unbounded join error"),
+    }
+    match comp
+        .scan()
+        .opt_until(usize::MAX)
+        .limit(comp.scan().opt_until(usize::MAX))
+    {
+        Err(Error::UnboundedJoin) => {}
+        _ => panic!("expected an unbounded join error"),
+    }
+    assert!(comp.scan().opt_until(usize::MAX).limit(comp.scan()).is_ok());
 }
@@ -1,2 +1,4 @@
+mod bit_set;
 mod component_query;
+mod system;
 mod world;
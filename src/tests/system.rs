@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use anymap::AnyMap;
+
+use component::*;
+use dense_component::*;
+use entity::*;
+use system::*;
+use world::*;
+use world_multi_lock::*;
+
+#[test]
+fn test_dispatcher() {
+    #[derive(Clone)]
+    struct Position(i32);
+    #[derive(Clone)]
+    struct Velocity(i32);
+    #[derive(Clone)]
+    struct Unregistered;
+
+    impl Component for Position {
+        type Storage = DenseComponentStorage<Self>;
+    }
+
+    impl Component for Velocity {
+        type Storage = DenseComponentStorage<Self>;
+    }
+
+    impl Component for Unregistered {
+        type Storage = DenseComponentStorage<Self>;
+    }
+
+    struct Entities(Vec<Entity>);
+    struct PositionSum(i32);
+    struct Runs(Arc<AtomicUsize>);
+
+    struct Integrate;
+
+    impl<'a> System<'a> for Integrate {
+        type Data = (
+            ReadResource<'a, Entities>,
+            ReadComponent<'a, Velocity>,
+            WriteComponent<'a, Position>,
+        );
+
+        fn run(
+            &mut self,
+            (entities, velocities, mut positions): <Self::Data as WorldMultiLocker<'a>>::Handles,
+        ) {
+            for &e in &entities.0 {
+                let velocity = velocities.get(e).unwrap().unwrap().0;
+                positions.get_mut(e).unwrap().unwrap().0 += velocity;
+            }
+        }
+    }
+
+    struct SumPositions;
+
+    impl<'a> System<'a> for SumPositions {
+        type Data = (
+            ReadResource<'a, Entities>,
+            ReadComponent<'a, Position>,
+            WriteResource<'a, PositionSum>,
+        );
+
+        fn run(
+            &mut self,
+            (entities, positions, mut sum): <Self::Data as WorldMultiLocker<'a>>::Handles,
+        ) {
+            sum.0 = entities
+                .0
+                .iter()
+                .map(|&e| positions.get(e).unwrap().unwrap().0)
+                .sum();
+        }
+    }
+
+    struct CountRuns;
+
+    impl<'a> System<'a> for CountRuns {
+        type Data = (ReadResource<'a, Runs>,);
+
+        fn run(&mut self, (runs,): <Self::Data as WorldMultiLocker<'a>>::Handles) {
+            runs.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct UseUnregistered;
+
+    impl<'a> System<'a> for UseUnregistered {
+        type Data = (ReadComponent<'a, Unregistered>,);
+
+        fn run(&mut self, _: <Self::Data as WorldMultiLocker<'a>>::Handles) {
+            panic!("system with an unregistered component should not run");
+        }
+    }
+
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+
+    let mut entities = Vec::new();
+    for i in 0..4 {
+        let mut components = AnyMap::new();
+        components.insert(Position(0));
+        components.insert(Velocity(i));
+        entities.push(world.add_entity(Some(components)).unwrap());
+    }
+    world.insert_resource(Entities(entities));
+    world.insert_resource(PositionSum(0));
+    world.insert_resource(Runs(runs.clone()));
+
+    let mut dispatcher = Dispatcher::with_thread_count(4);
+    dispatcher.add_system(Integrate);
+    dispatcher.add_system(CountRuns);
+    dispatcher.add_system(SumPositions);
+    dispatcher.add_system(CountRuns);
+
+    for i in 1..4 {
+        dispatcher.dispatch(&world).unwrap();
+        assert_eq!(world.read_resource::<PositionSum>().unwrap().0, 6 * i);
+        assert_eq!(runs.load(Ordering::SeqCst), 2 * i as usize);
+    }
+
+    dispatcher.add_system(UseUnregistered);
+    assert!(dispatcher.dispatch(&world).is_err());
+    assert_eq!(world.read_resource::<PositionSum>().unwrap().0, 24);
+    assert_eq!(runs.load(Ordering::SeqCst), 8);
+}
+
+#[test]
+fn test_dispatcher_parallel() {
+    // Each system waits for the other to start, which only happens if they run at the same time.
+    struct Rendezvous {
+        arrived: Mutex<usize>,
+        all_arrived: Condvar,
+        timed_out: AtomicBool,
+    }
+
+    struct Meet;
+
+    impl<'a> System<'a> for Meet {
+        type Data = (ReadResource<'a, Rendezvous>,);
+
+        fn run(&mut self, (rendezvous,): <Self::Data as WorldMultiLocker<'a>>::Handles) {
+            let mut arrived = rendezvous.arrived.lock().unwrap();
+            *arrived += 1;
+            rendezvous.all_arrived.notify_all();
+            let (_arrived, timeout) = rendezvous
+                .all_arrived
+                .wait_timeout_while(arrived, Duration::from_secs(5), |arrived| *arrived < 2)
+                .unwrap();
+            if timeout.timed_out() {
+                rendezvous.timed_out.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    let mut world = World::new();
+    world.insert_resource(Rendezvous {
+        arrived: Mutex::new(0),
+        all_arrived: Condvar::new(),
+        timed_out: AtomicBool::new(false),
+    });
+
+    let mut dispatcher = Dispatcher::with_thread_count(2);
+    dispatcher.add_system(Meet);
+    dispatcher.add_system(Meet);
+    dispatcher.dispatch(&world).unwrap();
+
+    let rendezvous = world.read_resource::<Rendezvous>().unwrap();
+    assert_eq!(*rendezvous.arrived.lock().unwrap(), 2);
+    assert!(!rendezvous.timed_out.load(Ordering::SeqCst));
+}
@@ -1,13 +1,65 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use anymap::AnyMap;
 
+use bundle::Bundle;
+use change_tick::TrackedMut;
+use command_buffer::*;
 use component::*;
+use component_hooks::*;
 use component_scanner::*;
 use dense_component::*;
+use ecs::Ecs;
 use entity::*;
+use error::Error;
+use query::*;
+use removal_log::*;
 use sparse_component::*;
 use world::*;
 use world_multi_lock::*;
 
+#[derive(Clone, PartialEq, Debug, Default)]
+struct Position(i32);
+#[derive(Clone, PartialEq, Debug)]
+struct Velocity(i32);
+#[derive(Clone, PartialEq, Debug)]
+struct Name(&'static str);
+#[derive(Clone)]
+struct Frozen;
+// Never registered.
+#[derive(Clone)]
+struct Unregistered;
+#[derive(Clone)]
+struct AlsoUnregistered;
+
+impl Component for Position {
+    type Storage = DenseComponentStorage<Self>;
+}
+
+impl Component for Velocity {
+    type Storage = SparseComponentStorage<Self>;
+}
+
+impl Component for Name {
+    type Storage = SparseComponentStorage<Self>;
+}
+
+impl Component for Frozen {
+    type Storage = SparseComponentStorage<Self>;
+}
+
+impl Component for Unregistered {
+    type Storage = DenseComponentStorage<Self>;
+}
+
+impl Component for AlsoUnregistered {
+    type Storage = DenseComponentStorage<Self>;
+}
+
+struct Score(i32);
+
 #[test]
 fn test_world() {
     #[derive(Clone)]
@@ -84,13 +136,17 @@ fn test_world() {
             .unwrap();
 
         // Query entities with all of a set of components
-        for (_pc, _vc) in component_scan_join((positions.scan_mut(), velocities.scan())).iter() {
+        for (_pc, _vc) in component_scan_join((positions.scan_mut(), velocities.scan()))
+            .unwrap()
+            .iter()
+        {
             // update position for velocity, for example
         }
 
         // All entities with positions and velocities, so there should be 11 total
         assert_eq!(
             component_scan_join((positions.scan_mut(), velocities.scan(),))
+                .unwrap()
                 .iter()
                 .count(),
             11
@@ -100,13 +156,16 @@ fn test_world() {
         {
             let (_player_position, _player_velocity) =
                 component_scan_join((positions.scan_mut(), velocities.scan()))
+                    .unwrap()
                     .limit(players.scan())
+                    .unwrap()
                     .singleton()
                     .unwrap();
         }
 
         // We can also invert queries to scan for all non-players
         for (_pc, _vc) in component_scan_join((positions.scan_mut(), velocities.scan()))
+            .unwrap()
             .not(players.scan())
             .iter()
         {
@@ -115,7 +174,8 @@ fn test_world() {
 
         // All entities with positions and *optionally* a velocity, so 16 in total
         assert_eq!(
-            component_scan_join((positions.scan_mut(), velocities.scan().opt(),))
+            component_scan_join((positions.scan_mut(), velocities.scan_opt(),))
+                .unwrap()
                 .iter()
                 .count(),
             16
@@ -125,7 +185,9 @@ fn test_world() {
         // velocities, so the count of this query should be 5.
         assert_eq!(
             component_scan_join((positions.scan_mut(), velocities.scan()))
+                .unwrap()
                 .limit(world.scan_entity_set(&tagged.0))
+                .unwrap()
                 .iter()
                 .count(),
             5
@@ -136,7 +198,1027 @@ fn test_world() {
             world.scan_entities(),
             positions.scan_mut(),
             velocities.scan(),
-        )).iter()
+        ))
+        .unwrap()
+        .iter()
         {}
     }
 }
+
+#[test]
+fn test_stale_entity() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct NameComponent(&'static str);
+
+    impl Component for NameComponent {
+        type Storage = DenseComponentStorage<Self>;
+    }
+
+    let mut world = World::new();
+    world.register_component::<NameComponent>();
+
+    let mut components = AnyMap::new();
+    components.insert(NameComponent("old"));
+    let old = world.add_entity(Some(components)).unwrap();
+    world.remove_entity(old).unwrap();
+
+    // The new entity re-uses the index of the old one, but the old Entity must not see its
+    // components.
+    let mut components = AnyMap::new();
+    components.insert(NameComponent("new"));
+    let new = world.add_entity(Some(components)).unwrap();
+    assert_eq!(old.index(), new.index());
+
+    let mut names = world.write_component::<NameComponent>().unwrap();
+    assert_eq!(names.get(new).unwrap(), Some(&NameComponent("new")));
+    assert!(names.get(old).is_err());
+    assert!(names.get_mut(old).is_err());
+    assert!(names.remove(old).is_err());
+    assert_eq!(names.remove(new).unwrap(), Some(NameComponent("new")));
+    assert_eq!(names.get(new).unwrap(), None);
+    drop(names);
+
+    assert!(world.insert_components(old, AnyMap::new()).is_err());
+    assert!(world.insert_components(new, AnyMap::new()).is_ok());
+}
+
+#[test]
+fn test_query() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    world.register_component::<Name>();
+    world.register_component::<Frozen>();
+
+    let mut entities = Vec::new();
+    for i in 0..6 {
+        let mut components = AnyMap::new();
+        components.insert(Position(0));
+        components.insert(Velocity(i));
+        if i % 2 == 0 {
+            components.insert(Name("even"));
+        }
+        if i == 4 {
+            components.insert(Frozen);
+        }
+        entities.push(world.add_entity(Some(components)).unwrap());
+    }
+    let mut components = AnyMap::new();
+    components.insert(Position(0));
+    world.add_entity(Some(components)).unwrap();
+
+    {
+        let mut query = world
+            .query::<(&Velocity, &mut Position, Without<Frozen>)>()
+            .unwrap();
+        for (v, mut p, ()) in &mut query {
+            p.0 += v.0;
+        }
+    }
+
+    {
+        let mut query = world.query::<(Entity, &Position, Option<&Name>)>().unwrap();
+        let results = query.iter().collect::<Vec<_>>();
+        assert_eq!(results.len(), 7);
+        assert_eq!(results[1], (entities[1], &Position(1), None));
+        assert_eq!(results[2], (entities[2], &Position(2), Some(&Name("even"))));
+        assert_eq!(results[4], (entities[4], &Position(0), Some(&Name("even"))));
+        assert_eq!(results[6].2, None);
+    }
+
+    // Queries of only optional terms are still bounded by the live entities
+    let mut query = world.query::<(Option<&Name>,)>().unwrap();
+    assert_eq!(query.iter().count(), 7);
+    assert_eq!(world.query::<(Entity,)>().unwrap().iter().count(), 7);
+}
+
+#[test]
+fn test_par_query() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    world.register_component::<Name>();
+    world.register_component::<Frozen>();
+
+    for i in 0..1000 {
+        let mut components = AnyMap::new();
+        components.insert(Position(0));
+        if i % 2 == 0 {
+            components.insert(Velocity(i));
+        }
+        if i % 3 == 0 {
+            components.insert(Name("third"));
+        }
+        if i % 5 == 0 {
+            components.insert(Frozen);
+        }
+        world.add_entity(Some(components)).unwrap();
+    }
+
+    {
+        let mut query = world
+            .query::<(
+                Entity,
+                &Velocity,
+                &mut Position,
+                Option<&Name>,
+                Without<Frozen>,
+            )>()
+            .unwrap();
+        query
+            .scan()
+            .par_scan()
+            .thread_count(4)
+            .batch_size(16)
+            .for_each(|(e, v, mut p, name, ()), index| {
+                assert_eq!(e.index(), index);
+                assert_eq!(name.is_some(), index % 3 == 0);
+                p.0 += v.0 + 1;
+            });
+    }
+
+    let mut query = world.query::<(Entity, &Position)>().unwrap();
+    for (e, p) in &mut query {
+        let i = e.index() as i32;
+        let expected = if i % 2 == 0 && i % 5 != 0 { i + 1 } else { 0 };
+        assert_eq!(p, &Position(expected));
+    }
+}
+
+#[test]
+fn test_command_buffer() {
+    struct Counter(i32);
+
+    let mut world = World::new();
+    world.register_component::<Position>();
+
+    let mut components = AnyMap::new();
+    components.insert(Position(0));
+    let existing = world.add_entity(Some(components)).unwrap();
+    let removed = world.add_entity(None).unwrap();
+    world.remove_entity(removed);
+
+    let commands = CommandBuffer::new();
+    let spawned = {
+        let world = &world;
+        let commands = &commands;
+        thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|i| {
+                    scope.spawn(move || {
+                        let entity = commands.spawn(world);
+                        commands.insert_component(entity, Position(i));
+                        entity
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Vec<_>>()
+        })
+    };
+
+    for &e in &spawned {
+        assert!(!world.entity_is_live(e));
+        assert!(e != existing);
+    }
+    let mut indexes = spawned.iter().map(|e| e.index()).collect::<Vec<_>>();
+    indexes.sort();
+    indexes.dedup();
+    assert_eq!(indexes.len(), 4);
+
+    commands.remove_entity(spawned[0]);
+    commands.insert_component(spawned[0], Position(100));
+    commands.insert_component(existing, Unregistered);
+    commands.remove_component::<Position>(existing);
+    commands.insert_resource(Counter(1));
+    assert_eq!(commands.len(), 9);
+
+    match world.apply_commands(&commands) {
+        Err(Error::UnregisteredComponent { .. }) => {}
+        _ => panic!("expected an unregistered component error"),
+    }
+    assert!(commands.is_empty());
+
+    assert!(!world.entity_is_live(spawned[0]));
+    {
+        let positions = world.read_component::<Position>().unwrap();
+        for (i, &e) in spawned.iter().enumerate().skip(1) {
+            assert_eq!(positions.get(e).unwrap().map(|p| p.0 as usize), Some(i));
+        }
+        assert_eq!(positions.get(existing).unwrap(), None);
+    }
+    assert_eq!(world.read_resource::<Counter>().unwrap().0, 1);
+
+    let entity = world.commands().spawn(&world);
+    world.commands().remove_resource::<Counter>();
+    world.maintain().unwrap();
+    assert!(world.entity_is_live(entity));
+    assert!(world.read_resource::<Counter>().is_err());
+
+    world
+        .commands()
+        .push(|_| Err(Error::command(format_err!("custom command failure"))));
+    match world.maintain() {
+        Err(Error::Command(err)) => assert_eq!(err.to_string(), "custom command failure"),
+        _ => panic!("expected a command error"),
+    }
+
+    // Commands recorded while the world's own buffer is applied are applied by the same maintain.
+    world.commands().push(|world| {
+        world.commands().insert_resource(Counter(2));
+        Ok(())
+    });
+    world.maintain().unwrap();
+    assert_eq!(world.read_resource::<Counter>().unwrap().0, 2);
+    assert!(world.commands().is_empty());
+}
+
+#[test]
+fn test_unregistered_insert() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+
+    let mut components = AnyMap::new();
+    components.insert(Position(1));
+    let entity = world.add_entity(Some(components)).unwrap();
+
+    let partial = || {
+        let mut components = AnyMap::new();
+        components.insert(Position(2));
+        components.insert(Unregistered);
+        components.insert(AlsoUnregistered);
+        components
+    };
+    match world.add_entity(Some(partial())) {
+        Err(Error::UnregisteredComponent { type_names }) => assert_eq!(type_names.len(), 2),
+        _ => panic!("expected an unregistered component error"),
+    }
+    assert_eq!(world.scan_entities().iter().count(), 1);
+
+    assert!(world.insert_components(entity, partial()).is_err());
+    assert_eq!(
+        world
+            .read_component::<Position>()
+            .unwrap()
+            .get(entity)
+            .unwrap(),
+        Some(&Position(1))
+    );
+
+    let err = world.read_component::<Unregistered>().err().unwrap();
+    assert!(err.to_string().contains("Unregistered"));
+
+    // Builders know the component types, so they are reported by name.
+    let err = world
+        .create_entity()
+        .with(Position(3))
+        .with(Unregistered)
+        .with(AlsoUnregistered)
+        .build()
+        .unwrap_err();
+    assert!(err.to_string().contains("::Unregistered\""));
+    assert!(err.to_string().contains("::AlsoUnregistered\""));
+
+    let commands = CommandBuffer::new();
+    commands.create_entity(&world).with(Unregistered).build();
+    let err = world.apply_commands(&commands).unwrap_err();
+    assert!(err.to_string().contains("::Unregistered\""));
+}
+
+#[test]
+fn test_errors() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    let entity = world.spawn((Position(1),)).unwrap();
+    world.remove_entity(entity);
+
+    match world.spawn((Position(2), Unregistered)) {
+        Err(Error::UnregisteredComponent { type_names }) => {
+            assert_eq!(type_names.len(), 1);
+            assert!(type_names[0].ends_with("::Unregistered"))
+        }
+        _ => panic!("expected an unregistered component error"),
+    }
+
+    match world.read_resource::<String>() {
+        Err(Error::MissingResource { type_name }) => assert_eq!(type_name, "alloc::string::String"),
+        _ => panic!("expected a missing resource error"),
+    }
+
+    match world.insert_bundle(entity, (Position(3),)) {
+        Err(Error::EntityDead(dead)) => assert_eq!(dead, entity),
+        _ => panic!("expected a dead entity error"),
+    }
+
+    let positions = world.read_component::<Position>().unwrap();
+    match positions.get(entity) {
+        Err(Error::EntityDead(dead)) => assert_eq!(dead, entity),
+        _ => panic!("expected a dead entity error"),
+    }
+}
+
+#[test]
+fn test_bundle() {
+    #[derive(PartialEq, Debug)]
+    struct Body {
+        position: Position,
+        velocity: Velocity,
+    }
+
+    impl_bundle!(Body {
+        position: Position,
+        velocity: Velocity,
+    });
+
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    world.register_component::<Name>();
+
+    let a = world.spawn((Position(1), Velocity(2))).unwrap();
+    let b = world
+        .spawn(Body {
+            position: Position(3),
+            velocity: Velocity(4),
+        })
+        .unwrap();
+
+    assert!(world.spawn((Position(5), Unregistered)).is_err());
+    assert_eq!(world.scan_entities().iter().count(), 2);
+    assert!(world.insert_bundle(a, (Name("a"), Unregistered)).is_err());
+    assert_eq!(world.remove_bundle::<(Name,)>(a).unwrap(), None);
+
+    assert_eq!(
+        world.insert_bundle(a, (Position(10), Name("a"))).unwrap(),
+        (Some(Position(1)), None)
+    );
+    assert_eq!(
+        world.remove_bundle::<Body>(a).unwrap(),
+        Some(Body {
+            position: Position(10),
+            velocity: Velocity(2),
+        })
+    );
+    assert_eq!(world.remove_bundle::<(Name, Position)>(a).unwrap(), None);
+    assert_eq!(
+        world.clone_entity_components(a).unwrap().get::<Name>(),
+        Some(&Name("a"))
+    );
+
+    assert_eq!(
+        world.remove_bundle::<(Velocity, Position)>(b).unwrap(),
+        Some((Velocity(4), Position(3)))
+    );
+
+    match world.spawn((Position(6), Name("c"), Position(7))) {
+        Err(Error::DuplicateComponent { type_name }) => assert!(type_name.ends_with("::Position")),
+        _ => panic!("expected a duplicate component error"),
+    }
+    match world.remove_bundle::<(Name, Name)>(a) {
+        Err(Error::DuplicateComponent { .. }) => {}
+        _ => panic!("expected a duplicate component error"),
+    }
+    assert_eq!(world.scan_entities().iter().count(), 2);
+
+    world.remove_entity(b);
+    assert!(world.insert_bundle(b, (Name("b"),)).is_err());
+    assert_eq!(world.remove_bundle::<(Name,)>(b).unwrap(), None);
+
+    // Inserting through the trait directly is just as atomic.
+    let mut ecs = Ecs::new();
+    ecs.register_component::<Position>();
+    let c = ecs.add_bundle((Position(1),)).unwrap();
+    assert!((Position(2), Unregistered).insert(&mut ecs, c).is_err());
+    assert!(<(Position,)>::contains(&ecs, c).unwrap());
+    assert!(!<(Position, Unregistered)>::contains(&ecs, c).unwrap());
+    assert_eq!(
+        ecs.read_component::<Position>().unwrap().get(c).unwrap(),
+        Some(&Position(1))
+    );
+}
+
+#[test]
+fn test_entity_builder() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    world.register_component::<Name>();
+
+    let a = world
+        .create_entity()
+        .with_default::<Position>()
+        .with(Velocity(1))
+        .maybe_with(Some(Name("a")))
+        .build()
+        .unwrap();
+    let b = world
+        .create_entity()
+        .with(Position(2))
+        .maybe_with::<Name>(None)
+        .build()
+        .unwrap();
+    assert!(world.create_entity().with(Unregistered).build().is_err());
+    assert_eq!(world.scan_entities().iter().count(), 2);
+
+    {
+        let (positions, names) = world
+            .multi_lock::<(ReadComponent<Position>, ReadComponent<Name>)>()
+            .unwrap();
+        assert_eq!(positions.get(a).unwrap(), Some(&Position(0)));
+        assert_eq!(positions.get(b).unwrap(), Some(&Position(2)));
+        assert_eq!(names.get(a).unwrap(), Some(&Name("a")));
+        assert_eq!(names.get(b).unwrap(), None);
+    }
+
+    let commands = CommandBuffer::new();
+    let c = commands
+        .create_entity(&world)
+        .with(Position(3))
+        .with(Name("c"))
+        .build();
+    let d = commands
+        .create_entity(&world)
+        .with(Position(4))
+        .with(Unregistered)
+        .build();
+    assert!(world.apply_commands(&commands).is_err());
+
+    // A failed build inserts nothing and removes its reserved entity.
+    assert!(world.entity_is_live(c));
+    assert!(!world.entity_is_live(d));
+    let positions = world.read_component::<Position>().unwrap();
+    assert_eq!(positions.get(c).unwrap(), Some(&Position(3)));
+    assert!(positions.get(d).is_err());
+}
+
+#[test]
+fn test_change_ticks() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    world.register_component::<Frozen>();
+
+    let entities = (0..5)
+        .map(|i| {
+            world
+                .create_entity()
+                .with(Position(i))
+                .with(Velocity(i))
+                .build()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let changed_since = |world: &World, tick: u64| {
+        let positions = world.read_component::<Position>().unwrap();
+        component_scan_join((world.scan_entities(), positions.scan_changed_since(tick)))
+            .unwrap()
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>()
+    };
+    let added_since = |world: &World, tick: u64| {
+        let positions = world.read_component::<Position>().unwrap();
+        component_scan_join((world.scan_entities(), positions.scan_added_since(tick)))
+            .unwrap()
+            .iter()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>()
+    };
+
+    let start = world.increment_change_tick();
+    assert_eq!(changed_since(&world, start - 1), entities);
+    assert_eq!(changed_since(&world, start), vec![]);
+
+    let tick = world.increment_change_tick();
+    {
+        let mut positions = world.write_component::<Position>().unwrap();
+        positions.get_mut(entities[3]).unwrap().unwrap().0 += 1;
+        positions.insert(entities[1], Position(10));
+    }
+    world.remove_entity(entities[4]);
+    let late = world.create_entity().with(Position(5)).build().unwrap();
+    assert_eq!(
+        changed_since(&world, tick),
+        vec![entities[1], entities[3], late]
+    );
+    assert_eq!(added_since(&world, tick), vec![late]);
+    assert_eq!(added_since(&world, start - 1).len(), 5);
+
+    let tick = world.increment_change_tick();
+    {
+        let mut positions = world.write_component::<Position>().unwrap();
+        for mut p in positions.scan_mut().iter().take(2) {
+            p.0 += 1;
+        }
+    }
+    assert_eq!(changed_since(&world, tick), vec![entities[0], entities[1]]);
+
+    // Only the components that are yielded and mutably dereferenced are marked as changed, not the
+    // ones that a join rejects or that are only read.
+    world
+        .write_component::<Frozen>()
+        .unwrap()
+        .insert(entities[2], Frozen);
+    let tick = world.increment_change_tick();
+    {
+        let mut positions = world.write_component::<Position>().unwrap();
+        let sum = positions.scan_mut().iter().map(|p| p.0).sum::<i32>();
+        assert_eq!(sum, 23);
+    }
+    assert_eq!(changed_since(&world, tick), vec![]);
+    {
+        let mut query = world
+            .query::<(&Velocity, &mut Position, Without<Frozen>)>()
+            .unwrap();
+        for (v, mut p, ()) in &mut query {
+            p.0 += v.0;
+        }
+    }
+    assert_eq!(
+        changed_since(&world, tick),
+        vec![entities[0], entities[1], entities[3]]
+    );
+}
+
+#[test]
+fn test_removal_log() {
+    #[derive(Clone, PartialEq, Debug)]
+    struct RigidBody(i32);
+
+    impl Component for RigidBody {
+        type Storage = SparseComponentStorage<Self>;
+    }
+
+    let mut world = World::new();
+    world.register_component::<RigidBody>();
+    world.register_component::<Position>();
+    world.track_removals::<RigidBody>(true).unwrap();
+
+    let entities = (0..4)
+        .map(|i| {
+            world
+                .create_entity()
+                .with(RigidBody(i))
+                .with(Position(i))
+                .build()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let read = |world: &World, reader: &mut RemovalReader| {
+        let bodies = world.read_component::<RigidBody>().unwrap();
+        bodies
+            .removals()
+            .unwrap()
+            .read(reader)
+            .map(|(e, b)| (e, b.cloned()))
+            .collect::<Vec<_>>()
+    };
+
+    let (mut a, mut b) = {
+        let bodies = world.read_component::<RigidBody>().unwrap();
+        let removals = bodies.removals().unwrap();
+        (removals.reader(), removals.reader())
+    };
+    assert!(world
+        .read_component::<Position>()
+        .unwrap()
+        .removals()
+        .is_none());
+
+    world
+        .write_component::<RigidBody>()
+        .unwrap()
+        .remove(entities[0])
+        .unwrap();
+    world.remove_entity(entities[1]);
+    world.remove_entity(entities[0]);
+
+    assert_eq!(
+        read(&world, &mut a),
+        vec![
+            (entities[0], Some(RigidBody(0))),
+            (entities[1], Some(RigidBody(1))),
+        ]
+    );
+    assert_eq!(read(&world, &mut a), vec![]);
+
+    world.maintain().unwrap();
+    world.remove_entity(entities[2]);
+    assert_eq!(read(&world, &mut b).len(), 3);
+
+    world.maintain().unwrap();
+    world.maintain().unwrap();
+    world.remove_entity(entities[3]);
+    assert_eq!(
+        read(&world, &mut a),
+        vec![(entities[3], Some(RigidBody(3)))]
+    );
+    assert_eq!(
+        read(&world, &mut b),
+        vec![(entities[3], Some(RigidBody(3)))]
+    );
+    assert_eq!(
+        world
+            .read_component::<RigidBody>()
+            .unwrap()
+            .removals()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[test]
+fn test_component_hooks() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hooks = {
+        let (insert, replace, remove) = (events.clone(), events.clone(), events.clone());
+        ComponentHooks::new()
+            .on_insert(move |e: Entity, n: &Name| {
+                insert
+                    .lock()
+                    .unwrap()
+                    .push(format!("insert {} {}", e.index(), n.0))
+            })
+            .on_replace(move |e: Entity, old: &Name, new: &Name| {
+                replace
+                    .lock()
+                    .unwrap()
+                    .push(format!("replace {} {} {}", e.index(), old.0, new.0))
+            })
+            .on_remove(move |e: Entity, n: &Name| {
+                remove
+                    .lock()
+                    .unwrap()
+                    .push(format!("remove {} {}", e.index(), n.0))
+            })
+    };
+
+    let mut world = World::new();
+    world.register_component_with_hooks(hooks);
+
+    let a = world.create_entity().with(Name("a")).build().unwrap();
+    let b = world.spawn((Name("b"),)).unwrap();
+    world
+        .write_component::<Name>()
+        .unwrap()
+        .insert(a, Name("c"));
+    let mut components = AnyMap::new();
+    components.insert(Name("d"));
+    world.insert_components(b, components).unwrap();
+    world.write_component::<Name>().unwrap().remove(a).unwrap();
+    world.remove_entity(b);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "insert 0 a",
+            "insert 1 b",
+            "replace 0 a c",
+            "replace 1 b d",
+            "remove 0 c",
+            "remove 1 d",
+        ]
+    );
+}
+
+#[test]
+fn test_scan_opt() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+
+    let a = world.spawn((Position(1),)).unwrap();
+    world.spawn((Velocity(2),)).unwrap();
+    let c = world.spawn((Position(3),)).unwrap();
+    world.remove_entity(c).unwrap();
+
+    let (positions, mut velocities) = world
+        .multi_lock::<(ReadComponent<Position>, WriteComponent<Velocity>)>()
+        .unwrap();
+    let values = component_scan_join((positions.scan_opt(), velocities.scan_mut_opt()))
+        .unwrap()
+        .iter()
+        .map(|(p, v)| (p, v.map(TrackedMut::into_mut)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        values,
+        vec![
+            (Some(&Position(1)), None),
+            (None, Some(&mut Velocity(2))),
+            (None, None)
+        ]
+    );
+    assert_eq!(
+        positions
+            .scan_opt()
+            .limit(world.scan_entities())
+            .unwrap()
+            .iter()
+            .count(),
+        2
+    );
+    assert!(positions.get(a).unwrap().is_some());
+}
+
+#[test]
+fn test_poisoned_locks() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.insert_resource(Score(0));
+    let entity = world.spawn((Position(1),)).unwrap();
+
+    thread::scope(|scope| {
+        let world = &world;
+        assert!(scope
+            .spawn(move || {
+                let _score = world.write_resource::<Score>().unwrap();
+                let _positions = world.write_component::<Position>().unwrap();
+                panic!("poisoning");
+            })
+            .join()
+            .is_err());
+    });
+
+    match world.read_component::<Position>() {
+        Err(Error::LockPoisoned { .. }) => {}
+        _ => panic!("expected a poisoned lock error"),
+    }
+    match world.write_resource::<Score>() {
+        Err(Error::LockPoisoned { .. }) => {}
+        _ => panic!("expected a poisoned lock error"),
+    }
+    assert!(world
+        .multi_lock::<(ReadResource<Score>, ReadComponent<Position>)>()
+        .is_err());
+
+    world.clear_component_poison::<Position>().unwrap();
+    assert_eq!(
+        world
+            .read_component::<Position>()
+            .unwrap()
+            .get(entity)
+            .unwrap(),
+        Some(&Position(1))
+    );
+    assert!(world.read_resource::<Score>().is_err());
+
+    world.clear_resource_poison::<Score>().unwrap();
+    assert_eq!(world.read_resource::<Score>().unwrap().0, 0);
+    assert!(world.clear_resource_poison::<String>().is_err());
+}
+
+#[test]
+fn test_poisoned_bundle() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.register_component::<Velocity>();
+    let entity = world.spawn((Velocity(0), Position(0))).unwrap();
+
+    thread::scope(|scope| {
+        let world = &world;
+        assert!(scope
+            .spawn(move || {
+                let _positions = world.write_component::<Position>().unwrap();
+                panic!("poisoning");
+            })
+            .join()
+            .is_err());
+    });
+
+    match world.spawn((Velocity(1), Position(1))) {
+        Err(Error::LockPoisoned { type_name }) => assert!(type_name.ends_with("::Position")),
+        _ => panic!("expected a poisoned lock error"),
+    }
+    assert_eq!(world.scan_entities().iter().count(), 1);
+
+    // Nothing is inserted or removed, even for the component types that are not poisoned.
+    assert!(world
+        .insert_bundle(entity, (Velocity(2), Position(2)))
+        .is_err());
+    assert!(world.remove_bundle::<(Velocity, Position)>(entity).is_err());
+    assert_eq!(
+        world
+            .read_component::<Velocity>()
+            .unwrap()
+            .get(entity)
+            .unwrap(),
+        Some(&Velocity(0))
+    );
+
+    world.clear_component_poison::<Position>().unwrap();
+    assert_eq!(
+        world.remove_bundle::<(Velocity, Position)>(entity).unwrap(),
+        Some((Velocity(0), Position(0)))
+    );
+}
+
+#[test]
+fn test_lock_timeouts() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.insert_resource(Score(0));
+
+    let positions = world.write_component::<Position>().unwrap();
+    thread::scope(|scope| {
+        let world = &world;
+        scope
+            .spawn(move || {
+                match world.try_read_component::<Position>() {
+                    Err(Error::WouldBlock { .. }) => {}
+                    _ => panic!("expected a would block error"),
+                }
+
+                let start = Instant::now();
+                match world.write_component_timeout::<Position>(Duration::from_millis(20)) {
+                    Err(Error::WouldBlock { .. }) => {}
+                    _ => panic!("expected a would block error"),
+                }
+                assert!(start.elapsed() >= Duration::from_millis(20));
+
+                // The resource is locked before the component, and must be released again when
+                // locking the component fails.
+                match world.multi_lock_timeout::<(WriteResource<Score>, ReadComponent<Position>)>(
+                    Duration::from_millis(1),
+                ) {
+                    Err(Error::WouldBlock { .. }) => {}
+                    _ => panic!("expected a would block error"),
+                }
+                world.try_write_resource::<Score>().unwrap().0 += 1;
+            })
+            .join()
+            .unwrap();
+    });
+    drop(positions);
+
+    assert!(world.try_read_component::<Position>().is_ok());
+    assert!(world
+        .try_multi_lock::<(ReadResource<Score>, WriteComponent<Position>)>()
+        .is_ok());
+    assert_eq!(
+        world
+            .read_resource_timeout::<Score>(Duration::from_millis(1))
+            .unwrap()
+            .0,
+        1
+    );
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn test_lock_order_tracking() {
+    use std::panic::{self, AssertUnwindSafe};
+
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.insert_resource(Score(0));
+
+    let panic_message = |f: &dyn Fn()| {
+        let err = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+        err.downcast::<String>().unwrap()
+    };
+
+    // Resources must be locked before components.
+    let message = panic_message(&|| {
+        let _positions = world.read_component::<Position>().unwrap();
+        let _score = world.read_resource::<Score>().unwrap();
+    });
+    assert!(message.contains("Score acquired while holding lock on"));
+    assert!(message.contains("Position"));
+
+    let message = panic_message(&|| {
+        let _positions = world.read_component::<Position>().unwrap();
+        let _positions = world.read_component::<Position>().unwrap();
+    });
+    assert!(message.contains("Position re-acquired"));
+
+    // Locks that cannot block forever are not checked, and released locks are forgotten.
+    {
+        let _positions = world.read_component::<Position>().unwrap();
+        assert!(world.try_read_resource::<Score>().is_ok());
+        assert!(world
+            .read_component_timeout::<Position>(Duration::from_millis(1))
+            .is_ok());
+    }
+    let _score = world.write_resource::<Score>().unwrap();
+    let _positions = world.write_component::<Position>().unwrap();
+}
+
+#[test]
+fn test_multi_lock_duplicates() {
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.insert_resource(Score(0));
+    let entity = world.create_entity().with(Position(1)).build().unwrap();
+
+    match world.multi_lock::<(ReadComponent<Position>, WriteComponent<Position>)>() {
+        Err(Error::ConflictingLocks { .. }) => {}
+        _ => panic!("expected a conflicting locks error"),
+    }
+    match world.multi_lock::<(
+        WriteResource<Score>,
+        ReadComponent<Position>,
+        WriteResource<Score>,
+    )>() {
+        Err(Error::ConflictingLocks { .. }) => {}
+        _ => panic!("expected a conflicting locks error"),
+    }
+    match world.query::<(&mut Position, Without<Position>)>() {
+        Err(Error::ConflictingLocks { .. }) => {}
+        _ => panic!("expected a conflicting locks error"),
+    }
+
+    {
+        let (a, b, score) = world
+            .multi_lock::<(
+                ReadComponent<Position>,
+                ReadComponent<Position>,
+                ReadResource<Score>,
+            )>()
+            .unwrap();
+        assert_eq!(a.get(entity).unwrap(), Some(&Position(1)));
+        assert_eq!(b.get(entity).unwrap(), Some(&Position(1)));
+        assert_eq!(score.0, 0);
+
+        // Shared guards can still be read from other threads, like plain read guards.
+        thread::scope(|scope| {
+            let (b, score) = (&b, &score);
+            scope.spawn(move || {
+                assert_eq!(b.get(entity).unwrap(), Some(&Position(1)));
+                assert_eq!(score.0, 0);
+            });
+        });
+
+        // Both reads share one guard, which is only released when both are dropped.
+        drop(a);
+        match world.try_write_component::<Position>() {
+            Err(Error::WouldBlock { .. }) => {}
+            _ => panic!("expected a would block error"),
+        }
+        drop(b);
+        world.try_write_component::<Position>().unwrap();
+    }
+
+    // Duplicate reads must not deadlock with a writer waiting on another thread.
+    thread::scope(|scope| {
+        let world = &world;
+        scope.spawn(move || {
+            for _ in 0..100 {
+                world.write_component::<Position>().unwrap();
+            }
+        });
+        for _ in 0..100 {
+            world
+                .multi_lock::<(ReadComponent<Position>, ReadComponent<Position>)>()
+                .unwrap();
+        }
+    });
+}
+
+#[test]
+fn test_optional_lockers() {
+    struct Overlay;
+
+    let mut world = World::new();
+    world.register_component::<Position>();
+    world.insert_resource(Score(0));
+    let entity = world.create_entity().with(Position(1)).build().unwrap();
+
+    {
+        let (mut score, overlay, mut positions, unregistered) = world
+            .multi_lock::<(
+                OptionalWriteResource<Score>,
+                OptionalReadResource<Overlay>,
+                OptionalWriteComponent<Position>,
+                OptionalReadComponent<Unregistered>,
+            )>()
+            .unwrap();
+        score.as_mut().unwrap().0 += 1;
+        assert!(overlay.is_none());
+        assert_eq!(
+            positions.as_mut().unwrap().get_mut(entity).unwrap(),
+            Some(&mut Position(1))
+        );
+        assert!(unregistered.is_none());
+    }
+    assert_eq!(world.read_resource::<Score>().unwrap().0, 1);
+
+    // Other errors still fail the whole lock.
+    let _score = world.write_resource::<Score>().unwrap();
+    thread::scope(|scope| {
+        let world = &world;
+        scope
+            .spawn(
+                move || match world.try_multi_lock::<(OptionalReadResource<Score>,)>() {
+                    Err(Error::WouldBlock { .. }) => {}
+                    _ => panic!("expected a would block error"),
+                },
+            )
+            .join()
+            .unwrap();
+    });
+}
@@ -1,66 +1,250 @@
-use std::any::TypeId;
-use std::sync::{LockResult, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::any::{type_name, TypeId};
+use std::ops::{Deref, DerefMut};
+use std::sync::{PoisonError, RwLock, RwLockWriteGuard};
+use std::time::Duration;
 
-use anymap::AnyMap;
-use failure::Error;
+use anymap::any::Any;
+use anymap::{AnyMap, Map};
 
+use bundle::Bundle;
+use command_buffer::CommandBuffer;
 use component::Component;
+use component_hooks::ComponentHooks;
 use ecs::{ComponentGetMutHandle, ComponentReadHandle, ComponentWriteHandle, Ecs};
 use entity::{Entity, EntityScanner, EntitySet, EntitySetScanner};
+use error::Error;
+use lock_tracker::HeldLock;
+use lock_wait::LockWait;
+use shared_read_guard::{SharedReadGuard, SharedReadLock};
+use world_multi_lock::LockId;
 
 pub struct World {
     ecs: Ecs,
-    resources: AnyMap,
+    resources: Map<dyn Any + Send + Sync>,
+    commands: CommandBuffer,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
     pub fn new() -> World {
         World {
             ecs: Ecs::new(),
-            resources: AnyMap::new(),
+            resources: Map::new(),
+            commands: CommandBuffer::new(),
         }
     }
 
-    pub fn insert_resource<T: 'static>(&mut self, resource: T) -> Option<T> {
+    pub fn insert_resource<T: 'static + Send + Sync>(&mut self, resource: T) -> Option<T> {
         self.resources
             .insert(ResourceEntry::new(resource))
-            .map(|r| r.into_inner().unwrap())
+            .map(ResourceEntry::into_inner)
     }
 
-    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+    pub fn remove_resource<T: 'static + Send + Sync>(&mut self) -> Option<T> {
         self.resources
             .remove::<ResourceEntry<T>>()
-            .map(|r| r.into_inner().unwrap())
+            .map(ResourceEntry::into_inner)
     }
 
-    pub fn read_resource<T: 'static>(&self) -> Result<RwLockReadGuard<T>, Error> {
-        let resource = self
-            .resources
-            .get::<ResourceEntry<T>>()
-            .ok_or_else(|| format_err!("No such resource {:?}", TypeId::of::<ResourceEntry<T>>()))?;
+    /// Returns an error if the resource is missing or poisoned, see `clear_resource_poison`.
+    pub fn read_resource<T: 'static + Send + Sync>(
+        &self,
+    ) -> Result<ResourceReadGuard<'_, T>, Error> {
+        self.read_resource_with(LockWait::Block)
+    }
 
-        Ok(resource.0.read().unwrap())
+    /// Returns `Error::WouldBlock` instead of waiting for the lock.
+    pub fn try_read_resource<T: 'static + Send + Sync>(
+        &self,
+    ) -> Result<ResourceReadGuard<'_, T>, Error> {
+        self.read_resource_with(LockWait::Try)
     }
 
-    pub fn write_resource<T: 'static>(&self) -> Result<RwLockWriteGuard<T>, Error> {
-        let resource = self
-            .resources
-            .get::<ResourceEntry<T>>()
-            .ok_or_else(|| format_err!("No such resource {:?}", TypeId::of::<ResourceEntry<T>>()))?;
-        Ok(resource.0.write().unwrap())
+    /// Returns `Error::WouldBlock` if the lock is not acquired within the given timeout.
+    pub fn read_resource_timeout<T: 'static + Send + Sync>(
+        &self,
+        timeout: Duration,
+    ) -> Result<ResourceReadGuard<'_, T>, Error> {
+        self.read_resource_with(LockWait::timeout(timeout))
+    }
+
+    pub fn read_resource_with<T: 'static + Send + Sync>(
+        &self,
+        wait: LockWait,
+    ) -> Result<ResourceReadGuard<'_, T>, Error> {
+        let lock = &self.resource_entry::<T>()?.0;
+        let held = hold_resource::<T>(wait);
+        let guard = wait.acquire::<T, _, _, _>(|| lock.read(), || lock.try_read())?;
+        Ok(ResourceReadGuard(SharedReadGuard::new(guard, held)))
+    }
+
+    /// Returns a guard that shares the given read lock on the resource `T`, which must come from
+    /// another `ResourceReadGuard<T>`.
+    pub(crate) fn share_resource<'a, T: 'static + Send + Sync>(
+        &'a self,
+        lock: SharedReadLock<'a>,
+    ) -> ResourceReadGuard<'a, T> {
+        ResourceReadGuard(SharedReadGuard::from_shared(lock))
+    }
+
+    pub fn write_resource<T: 'static + Send + Sync>(
+        &self,
+    ) -> Result<ResourceWriteGuard<'_, T>, Error> {
+        self.write_resource_with(LockWait::Block)
+    }
+
+    /// Returns `Error::WouldBlock` instead of waiting for the lock.
+    pub fn try_write_resource<T: 'static + Send + Sync>(
+        &self,
+    ) -> Result<ResourceWriteGuard<'_, T>, Error> {
+        self.write_resource_with(LockWait::Try)
+    }
+
+    /// Returns `Error::WouldBlock` if the lock is not acquired within the given timeout.
+    pub fn write_resource_timeout<T: 'static + Send + Sync>(
+        &self,
+        timeout: Duration,
+    ) -> Result<ResourceWriteGuard<'_, T>, Error> {
+        self.write_resource_with(LockWait::timeout(timeout))
+    }
+
+    pub fn write_resource_with<T: 'static + Send + Sync>(
+        &self,
+        wait: LockWait,
+    ) -> Result<ResourceWriteGuard<'_, T>, Error> {
+        let lock = &self.resource_entry::<T>()?.0;
+        let held = hold_resource::<T>(wait);
+        Ok(ResourceWriteGuard {
+            guard: wait.acquire::<T, _, _, _>(|| lock.write(), || lock.try_write())?,
+            _held: held,
+        })
+    }
+
+    /// Clears the poisoned state of a resource, left by a panic while it was locked for writing, so
+    /// that it can be accessed again.  The resource is left as the panic left it.
+    pub fn clear_resource_poison<T: 'static + Send + Sync>(&self) -> Result<(), Error> {
+        self.resource_entry::<T>()?.0.clear_poison();
+        Ok(())
+    }
+
+    /// Clears the poisoned state of a component storage, see `Ecs::clear_component_poison`.
+    pub fn clear_component_poison<T: Component>(&self) -> Result<(), Error> {
+        self.ecs.clear_component_poison::<T>()
+    }
+
+    /// The current world change tick, see `Ecs::change_tick`.
+    pub fn change_tick(&self) -> u64 {
+        self.ecs.change_tick()
+    }
+
+    /// Advances the world change tick and returns the previous one, see
+    /// `Ecs::increment_change_tick`.
+    pub fn increment_change_tick(&self) -> u64 {
+        self.ecs.increment_change_tick()
     }
 
     pub fn register_component<T: Component>(&mut self) {
         self.ecs.register_component::<T>();
     }
 
+    /// Registers the component type if it is not already registered, and sets its hooks.
+    pub fn register_component_with_hooks<T: Component>(&mut self, hooks: ComponentHooks<T>) {
+        self.ecs.register_component_with_hooks(hooks);
+    }
+
+    /// Starts logging removals of the given component type, see `Ecs::track_removals`.
+    pub fn track_removals<T: Component>(&mut self, keep_values: bool) -> Result<(), Error> {
+        self.ecs.track_removals::<T>(keep_values)
+    }
+
+    /// Starts building a new entity, see `EntityBuilder`.
+    pub fn create_entity(&mut self) -> EntityBuilder<'_> {
+        EntityBuilder {
+            world: self,
+            components: AnyMap::new(),
+            type_names: Vec::new(),
+        }
+    }
+
     pub fn add_entity(&mut self, components: Option<AnyMap>) -> Result<Entity, Error> {
-        Ok(self.ecs.add_entity(components)?)
+        self.ecs.add_entity(components)
     }
 
+    /// Inserts the given components into a live entity, returns an error if the entity is dead.
     pub fn insert_components(&mut self, entity: Entity, components: AnyMap) -> Result<(), Error> {
-        self.ecs.insert_components(entity, components)?;
-        Ok(())
+        self.insert_named_components(entity, components, &[])
+    }
+
+    pub(crate) fn insert_named_components(
+        &mut self,
+        entity: Entity,
+        components: AnyMap,
+        type_names: &[(TypeId, &'static str)],
+    ) -> Result<(), Error> {
+        match self
+            .ecs
+            .insert_named_components(entity, components, type_names)?
+        {
+            Some(_) => Ok(()),
+            None => Err(Error::EntityDead(entity)),
+        }
+    }
+
+    /// Adds a new entity with the components in the given bundle.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Result<Entity, Error> {
+        self.ecs.add_bundle(bundle)
+    }
+
+    /// Inserts the components in the given bundle into a live entity and returns the overwritten
+    /// components, returns an error if the entity is dead.
+    pub fn insert_bundle<B: Bundle>(
+        &mut self,
+        entity: Entity,
+        bundle: B,
+    ) -> Result<B::Replaced, Error> {
+        self.ecs
+            .insert_bundle(entity, bundle)?
+            .ok_or(Error::EntityDead(entity))
+    }
+
+    /// Removes the components in a bundle from an entity only if it has all of them.
+    pub fn remove_bundle<B: Bundle>(&mut self, entity: Entity) -> Result<Option<B>, Error> {
+        self.ecs.remove_bundle(entity)
+    }
+
+    /// Reserves an entity through a shared reference, see `Ecs::reserve_entity`.
+    pub fn reserve_entity(&self) -> Entity {
+        self.ecs.reserve_entity()
+    }
+
+    pub fn allocate_reserved_entities(&mut self) {
+        self.ecs.allocate_reserved_entities()
+    }
+
+    /// The world's own `CommandBuffer`, which is applied by `maintain`.
+    pub fn commands(&self) -> &CommandBuffer {
+        &self.commands
+    }
+
+    /// Applies the commands recorded in the world's own `CommandBuffer`, makes every reserved
+    /// entity live, and trims the removal logs of every component type.  Commands recorded into the world's own buffer while it is being applied are applied too.
+    pub fn maintain(&mut self) -> Result<(), Error> {
+        let mut result = self.run_commands(self.commands.take());
+        loop {
+            let commands = self.commands.take();
+            if commands.is_empty() {
+                break;
+            }
+            let next = self.run_commands(commands);
+            result = result.and(next);
+        }
+        self.ecs.trim_removals();
+        result
     }
 
     pub fn remove_entity(&mut self, entity: Entity) -> Option<AnyMap> {
@@ -75,7 +259,7 @@ impl World {
         self.ecs.clone_entity_components(entity)
     }
 
-    pub fn scan_entities(&self) -> EntityScanner {
+    pub fn scan_entities(&self) -> EntityScanner<'_> {
         self.ecs.scan_entities()
     }
 
@@ -87,27 +271,164 @@ impl World {
         self.ecs.prune_entity_set(set)
     }
 
-    pub fn read_component<T: Component>(&self) -> Result<ComponentReadHandle<T>, Error> {
-        Ok(self.ecs.read_component::<T>()?)
+    pub fn read_component<T: Component>(&self) -> Result<ComponentReadHandle<'_, T>, Error> {
+        self.ecs.read_component::<T>()
+    }
+
+    /// Returns `Error::WouldBlock` instead of waiting for the lock.
+    pub fn try_read_component<T: Component>(&self) -> Result<ComponentReadHandle<'_, T>, Error> {
+        self.ecs.read_component_with::<T>(LockWait::Try)
+    }
+
+    /// Returns `Error::WouldBlock` if the lock is not acquired within the given timeout.
+    pub fn read_component_timeout<T: Component>(
+        &self,
+        timeout: Duration,
+    ) -> Result<ComponentReadHandle<'_, T>, Error> {
+        self.ecs
+            .read_component_with::<T>(LockWait::timeout(timeout))
+    }
+
+    pub fn read_component_with<T: Component>(
+        &self,
+        wait: LockWait,
+    ) -> Result<ComponentReadHandle<'_, T>, Error> {
+        self.ecs.read_component_with::<T>(wait)
+    }
+
+    pub fn write_component<T: Component>(&self) -> Result<ComponentWriteHandle<'_, T>, Error> {
+        self.ecs.write_component::<T>()
+    }
+
+    /// Returns `Error::WouldBlock` instead of waiting for the lock.
+    pub fn try_write_component<T: Component>(&self) -> Result<ComponentWriteHandle<'_, T>, Error> {
+        self.ecs.write_component_with::<T>(LockWait::Try)
+    }
+
+    /// Returns `Error::WouldBlock` if the lock is not acquired within the given timeout.
+    pub fn write_component_timeout<T: Component>(
+        &self,
+        timeout: Duration,
+    ) -> Result<ComponentWriteHandle<'_, T>, Error> {
+        self.ecs
+            .write_component_with::<T>(LockWait::timeout(timeout))
+    }
+
+    pub fn write_component_with<T: Component>(
+        &self,
+        wait: LockWait,
+    ) -> Result<ComponentWriteHandle<'_, T>, Error> {
+        self.ecs.write_component_with::<T>(wait)
     }
 
-    pub fn write_component<T: Component>(&self) -> Result<ComponentWriteHandle<T>, Error> {
-        Ok(self.ecs.write_component::<T>()?)
+    /// Returns a handle that shares the given read lock on the storage for `T`, which must come
+    /// from another `ComponentReadHandle<T>`.
+    pub(crate) fn share_component<'a, T: Component>(
+        &'a self,
+        lock: SharedReadLock<'a>,
+    ) -> ComponentReadHandle<'a, T> {
+        self.ecs.share_component(lock)
     }
 
-    pub fn get_mut_component<T: Component>(&mut self) -> Result<ComponentGetMutHandle<T>, Error> {
-        Ok(self.ecs.get_mut_component::<T>()?)
+    pub fn get_mut_component<T: Component>(
+        &mut self,
+    ) -> Result<ComponentGetMutHandle<'_, T>, Error> {
+        self.ecs.get_mut_component::<T>()
+    }
+
+    fn resource_entry<T: 'static + Send + Sync>(&self) -> Result<&ResourceEntry<T>, Error> {
+        self.resources
+            .get::<ResourceEntry<T>>()
+            .ok_or_else(Error::missing_resource::<T>)
     }
 }
 
+/// Collects components for a new entity, which is added with all of them by `build`.
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    components: AnyMap,
+    type_names: Vec<(TypeId, &'static str)>,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<T: Component>(mut self, component: T) -> Self {
+        self.components.insert(component);
+        self.type_names.push((TypeId::of::<T>(), type_name::<T>()));
+        self
+    }
+
+    pub fn with_default<T: Component + Default>(self) -> Self {
+        self.with(T::default())
+    }
+
+    pub fn maybe_with<T: Component>(self, component: Option<T>) -> Self {
+        match component {
+            Some(component) => self.with(component),
+            None => self,
+        }
+    }
+
+    /// Adds the entity, if any of the component types are unregistered returns an error without
+    /// adding it.
+    pub fn build(self) -> Result<Entity, Error> {
+        self.world
+            .ecs
+            .add_named_entity(Some(self.components), &self.type_names)
+    }
+}
+
+/// A read lock on a resource, returned by `World::read_resource`.
+pub struct ResourceReadGuard<'a, T: 'a>(SharedReadGuard<'a, T>);
+
+/// A write lock on a resource, returned by `World::write_resource`.
+pub struct ResourceWriteGuard<'a, T: 'a> {
+    guard: RwLockWriteGuard<'a, T>,
+    _held: HeldLock,
+}
+
+impl<'a, T: 'static + Sync> ResourceReadGuard<'a, T> {
+    /// Returns the guard along with its lock, for other guards of the same resource to share.
+    pub(crate) fn into_shared(self) -> (ResourceReadGuard<'a, T>, SharedReadLock<'a>) {
+        let (guard, lock) = self.0.into_shared();
+        (ResourceReadGuard(guard), lock)
+    }
+}
+
+impl<'a, T: 'static> Deref for ResourceReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<'a, T> Deref for ResourceWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<'a, T> DerefMut for ResourceWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+fn hold_resource<T: 'static>(wait: LockWait) -> HeldLock {
+    HeldLock::acquire(LockId::Resource(TypeId::of::<T>()), type_name::<T>(), wait)
+}
+
 struct ResourceEntry<T>(RwLock<T>);
 
-impl<T: 'static> ResourceEntry<T> {
+impl<T: 'static + Send + Sync> ResourceEntry<T> {
     fn new(r: T) -> ResourceEntry<T> {
         ResourceEntry::<T>(RwLock::new(r))
     }
 
-    fn into_inner(self) -> LockResult<T> {
-        self.0.into_inner()
+    // A removed or replaced resource is returned even if it is poisoned.
+    fn into_inner(self) -> T {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
     }
 }
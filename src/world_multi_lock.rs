@@ -1,45 +1,113 @@
-use std::any::TypeId;
+use std::any::{type_name, TypeId};
 use std::cell::RefCell;
-use std::sync::{RwLockReadGuard, RwLockWriteGuard};
-
-use failure::Error;
+use std::time::Duration;
 
 use component::Component;
 use ecs::{ComponentReadHandle, ComponentWriteHandle};
-use world::World;
+use error::Error;
+use lock_wait::LockWait;
+use shared_read_guard::SharedReadLock;
+use world::{ResourceReadGuard, ResourceWriteGuard, World};
 
 /// Locks must be acquired in this order, resources before components, and in TypeId order.
-#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Clone, Copy, Debug)]
 pub enum LockId {
     Resource(TypeId),
     Component(TypeId),
 }
 
+/// Whether a lock is shared (read) or exclusive (write).
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub enum LockAccess {
+    Read,
+    Write,
+}
+
 pub trait WorldLocker<'a> {
     type Handle;
 
     fn id(&self) -> LockId;
-    fn lock(&self, world: &'a World) -> Result<(), Error>;
-    // Will panic unless 'lock' has been called
+    fn access(&self) -> LockAccess;
+    /// The name of the locked type, for errors.
+    fn type_name(&self) -> &'static str;
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error>;
+    /// Shares the read lock this locker holds, if any, with other lockers of the same lock.
+    fn shared(&self) -> Option<SharedReadLock<'a>>;
+    /// Locks by sharing a read lock from another locker with the same `LockId`, only called for
+    /// read lockers.
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>);
+    // Will panic unless 'lock' or 'lock_shared' has been called
     fn handle(self) -> Self::Handle;
 }
 
+/// The parts of a `WorldLocker` that do not depend on its handle type, so that lockers of
+/// different types can be locked together.
+pub trait ErasedLocker<'a> {
+    fn type_name(&self) -> &'static str;
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error>;
+    fn shared(&self) -> Option<SharedReadLock<'a>>;
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>);
+}
+
+impl<'a, L: WorldLocker<'a>> ErasedLocker<'a> for L {
+    fn type_name(&self) -> &'static str {
+        WorldLocker::type_name(self)
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        WorldLocker::lock(self, world, wait)
+    }
+
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        WorldLocker::shared(self)
+    }
+
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>) {
+        WorldLocker::lock_shared(self, world, lock)
+    }
+}
+
+/// Locks a single entry in a `World`, returned by `WorldMultiLocker::lockers`.
+pub type LockerRef<'a, 'b> = &'b dyn ErasedLocker<'a>;
+
 pub trait WorldMultiLocker<'a> {
     type Handles;
 
-    fn lockers<'b>(&'b self) -> Vec<(LockId, Box<FnMut(&'a World) -> Result<(), Error> + 'b>)>;
+    fn lockers<'b>(&'b self) -> Vec<(LockId, LockAccess, LockerRef<'a, 'b>)>;
     // Will panic unless all locker methods have been called
     fn handles(self) -> Self::Handles;
 
-    fn lock(self, world: &'a World) -> Result<Self::Handles, Error>
+    /// Every lock that would be acquired by `lock`, and how.
+    fn accesses(&self) -> Vec<(LockId, LockAccess)> {
+        self.lockers()
+            .into_iter()
+            .map(|(id, access, _)| (id, access))
+            .collect()
+    }
+
+    /// Acquires every lock, waiting for each one as given by `wait`, where a deadline applies to
+    /// the acquisition of all of the locks together.  If any lock fails, every lock that was
+    /// already acquired is released before returning the error.
+    ///
+    /// The same lock may be requested more than once only if every request is a read, and then
+    /// every request shares one guard, otherwise this returns `Error::ConflictingLocks` without
+    /// acquiring anything.
+    fn lock(self, world: &'a World, wait: LockWait) -> Result<Self::Handles, Error>
     where
         Self: Sized,
     {
         {
             let mut lockers = self.lockers();
-            lockers.sort_by(|a, b| a.0.cmp(&b.0));
-            for (_, mut locker) in lockers {
-                locker(world)?;
+            lockers.sort_by_key(|a| a.0);
+            for group in lockers.chunk_by(|a, b| a.0 == b.0) {
+                if group.len() > 1 && group.iter().any(|l| l.1 == LockAccess::Write) {
+                    return Err(Error::ConflictingLocks {
+                        type_name: group[0].2.type_name(),
+                    });
+                }
+            }
+            for group in lockers.chunk_by(|a, b| a.0 == b.0) {
+                lock_group(group, world, wait)?;
             }
         }
 
@@ -47,6 +115,43 @@ pub trait WorldMultiLocker<'a> {
     }
 }
 
+// Locks a group of lockers of the same lock, which are all reads if there is more than one.  A std
+// `RwLock` blocks new readers while a writer is waiting, so a thread that waits for a second read
+// of a lock it already reads can deadlock.  Instead, every read in the group shares the guard
+// taken by the first.
+fn lock_group<'a, 'b>(
+    group: &[(LockId, LockAccess, LockerRef<'a, 'b>)],
+    world: &'a World,
+    wait: LockWait,
+) -> Result<(), Error> {
+    let (first, rest) = group.split_first().expect("lock group is empty");
+    first.2.lock(world, wait)?;
+    if rest.is_empty() {
+        return Ok(());
+    }
+    match first.2.shared() {
+        Some(lock) => {
+            for l in rest {
+                l.2.lock_shared(world, lock.clone());
+            }
+            Ok(())
+        }
+        // Nothing was locked because the resource is missing or the component is unregistered,
+        // so the rest of the group has nothing to wait for either.
+        None => rest.iter().try_for_each(|l| l.2.lock(world, wait)),
+    }
+}
+
+// Turns a locked read handle into one that shares its lock, and returns the lock.
+fn share<'a, H>(
+    handle: &mut Option<H>,
+    into_shared: fn(H) -> (H, SharedReadLock<'a>),
+) -> Option<SharedReadLock<'a>> {
+    let (shared, lock) = into_shared(handle.take()?);
+    *handle = Some(shared);
+    Some(lock)
+}
+
 macro_rules! impl_tuple {
     ($($locker:ident)*) => (
         impl<'a, $($locker,)*> WorldMultiLocker<'a> for ($($locker,)*)
@@ -55,10 +160,10 @@ macro_rules! impl_tuple {
             type Handles = ($($locker::Handle,)*);
 
             #[allow(non_snake_case)]
-            fn lockers<'b>(&'b self) -> Vec<(LockId, Box<FnMut(&'a World) -> Result<(), Error> + 'b>)> {
-                let mut lockers = Vec::<(LockId, Box<FnMut(&'a World) -> Result<(), Error> + 'b>)>::new();
+            fn lockers<'b>(&'b self) -> Vec<(LockId, LockAccess, LockerRef<'a, 'b>)> {
+                let mut lockers = Vec::<(LockId, LockAccess, LockerRef<'a, 'b>)>::new();
                 let ($(ref $locker,)*) = *self;
-                $(lockers.push(($locker.id(), Box::new(move |world| $locker.lock(world))));)*
+                $(lockers.push(($locker.id(), $locker.access(), $locker));)*
                 lockers
             }
 
@@ -71,22 +176,22 @@ macro_rules! impl_tuple {
     );
 }
 
-impl_tuple!{A}
-impl_tuple!{A B}
-impl_tuple!{A B C}
-impl_tuple!{A B C D}
-impl_tuple!{A B C D E}
-impl_tuple!{A B C D E F}
-impl_tuple!{A B C D E F G}
-impl_tuple!{A B C D E F G H}
-impl_tuple!{A B C D E F G H I}
-impl_tuple!{A B C D E F G H I J}
-impl_tuple!{A B C D E F G H I J K}
-impl_tuple!{A B C D E F G H I J K L}
-impl_tuple!{A B C D E F G H I J K L M}
-impl_tuple!{A B C D E F G H I J K L M N}
-impl_tuple!{A B C D E F G H I J K L M N O}
-impl_tuple!{A B C D E F G H I J K L M N O P}
+impl_tuple! {A}
+impl_tuple! {A B}
+impl_tuple! {A B C}
+impl_tuple! {A B C D}
+impl_tuple! {A B C D E}
+impl_tuple! {A B C D E F}
+impl_tuple! {A B C D E F G}
+impl_tuple! {A B C D E F G H}
+impl_tuple! {A B C D E F G H I}
+impl_tuple! {A B C D E F G H I J}
+impl_tuple! {A B C D E F G H I J K}
+impl_tuple! {A B C D E F G H I J K L}
+impl_tuple! {A B C D E F G H I J K L M}
+impl_tuple! {A B C D E F G H I J K L M N}
+impl_tuple! {A B C D E F G H I J K L M N O}
+impl_tuple! {A B C D E F G H I J K L M N O P}
 
 impl World {
     /// Lock multiple resources and components from a World, but always lock them in the correct
@@ -95,55 +200,104 @@ impl World {
     pub fn multi_lock<'a, M: Default + WorldMultiLocker<'a>>(
         &'a self,
     ) -> Result<M::Handles, Error> {
-        M::default().lock(self)
+        M::default().lock(self, LockWait::Block)
+    }
+
+    /// Returns `Error::WouldBlock` instead of waiting for any of the locks, releasing any locks it
+    /// already acquired.
+    pub fn try_multi_lock<'a, M: Default + WorldMultiLocker<'a>>(
+        &'a self,
+    ) -> Result<M::Handles, Error> {
+        M::default().lock(self, LockWait::Try)
+    }
+
+    /// Returns `Error::WouldBlock` if every lock is not acquired within the given timeout,
+    /// releasing any locks it already acquired.
+    pub fn multi_lock_timeout<'a, M: Default + WorldMultiLocker<'a>>(
+        &'a self,
+        timeout: Duration,
+    ) -> Result<M::Handles, Error> {
+        M::default().lock(self, LockWait::timeout(timeout))
     }
 }
 
-pub struct ReadResource<'a, T: 'static>(RefCell<Option<RwLockReadGuard<'a, T>>>);
+pub struct ReadResource<'a, T: 'static + Send + Sync>(RefCell<Option<ResourceReadGuard<'a, T>>>);
 
-impl<'a, T: 'static> Default for ReadResource<'a, T> {
+impl<'a, T: 'static + Send + Sync> Default for ReadResource<'a, T> {
     fn default() -> Self {
         ReadResource(RefCell::new(None))
     }
 }
 
-pub struct WriteResource<'a, T: 'static>(RefCell<Option<RwLockWriteGuard<'a, T>>>);
+pub struct WriteResource<'a, T: 'static + Send + Sync>(RefCell<Option<ResourceWriteGuard<'a, T>>>);
 
-impl<'a, T: 'static> Default for WriteResource<'a, T> {
+impl<'a, T: 'static + Send + Sync> Default for WriteResource<'a, T> {
     fn default() -> Self {
         WriteResource(RefCell::new(None))
     }
 }
 
-impl<'a, T: 'static> WorldLocker<'a> for ReadResource<'a, T> {
-    type Handle = RwLockReadGuard<'a, T>;
+impl<'a, T: 'static + Send + Sync> WorldLocker<'a> for ReadResource<'a, T> {
+    type Handle = ResourceReadGuard<'a, T>;
 
     fn id(&self) -> LockId {
         LockId::Resource(TypeId::of::<T>())
     }
 
-    fn lock(&self, world: &'a World) -> Result<(), Error> {
-        *self.0.borrow_mut() = Some(world.read_resource::<T>()?);
+    fn access(&self) -> LockAccess {
+        LockAccess::Read
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(world.read_resource_with::<T>(wait)?);
         Ok(())
     }
 
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        share(&mut self.0.borrow_mut(), ResourceReadGuard::into_shared)
+    }
+
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>) {
+        *self.0.borrow_mut() = Some(world.share_resource(lock));
+    }
+
     fn handle(self) -> Self::Handle {
         self.0.into_inner().unwrap()
     }
 }
 
-impl<'a, T: 'static> WorldLocker<'a> for WriteResource<'a, T> {
-    type Handle = RwLockWriteGuard<'a, T>;
+impl<'a, T: 'static + Send + Sync> WorldLocker<'a> for WriteResource<'a, T> {
+    type Handle = ResourceWriteGuard<'a, T>;
 
     fn id(&self) -> LockId {
         LockId::Resource(TypeId::of::<T>())
     }
 
-    fn lock(&self, world: &'a World) -> Result<(), Error> {
-        *self.0.borrow_mut() = Some(world.write_resource::<T>()?);
+    fn access(&self) -> LockAccess {
+        LockAccess::Write
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(world.write_resource_with::<T>(wait)?);
         Ok(())
     }
 
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        None
+    }
+
+    fn lock_shared(&self, _: &'a World, _: SharedReadLock<'a>) {
+        unreachable!("write locks are never shared")
+    }
+
     fn handle(self) -> Self::Handle {
         self.0.into_inner().unwrap()
     }
@@ -172,11 +326,27 @@ impl<'a, T: Component> WorldLocker<'a> for ReadComponent<'a, T> {
         LockId::Component(TypeId::of::<T>())
     }
 
-    fn lock(&self, world: &'a World) -> Result<(), Error> {
-        *self.0.borrow_mut() = Some(world.read_component::<T>()?);
+    fn access(&self) -> LockAccess {
+        LockAccess::Read
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(world.read_component_with::<T>(wait)?);
         Ok(())
     }
 
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        share(&mut self.0.borrow_mut(), ComponentReadHandle::into_shared)
+    }
+
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>) {
+        *self.0.borrow_mut() = Some(world.share_component(lock));
+    }
+
     fn handle(self) -> Self::Handle {
         self.0.into_inner().unwrap()
     }
@@ -189,11 +359,218 @@ impl<'a, T: Component> WorldLocker<'a> for WriteComponent<'a, T> {
         LockId::Component(TypeId::of::<T>())
     }
 
-    fn lock(&self, world: &'a World) -> Result<(), Error> {
-        *self.0.borrow_mut() = Some(world.write_component::<T>()?);
+    fn access(&self) -> LockAccess {
+        LockAccess::Write
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(world.write_component_with::<T>(wait)?);
         Ok(())
     }
 
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        None
+    }
+
+    fn lock_shared(&self, _: &'a World, _: SharedReadLock<'a>) {
+        unreachable!("write locks are never shared")
+    }
+
+    fn handle(self) -> Self::Handle {
+        self.0.into_inner().unwrap()
+    }
+}
+
+// Turns the error for an unregistered component or a missing resource into `None`.
+fn optional<H>(result: Result<H, Error>) -> Result<Option<H>, Error> {
+    match result {
+        Ok(handle) => Ok(Some(handle)),
+        Err(Error::UnregisteredComponent { .. }) | Err(Error::MissingResource { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Like `ReadResource`, but yields `None` instead of failing if the resource is missing.
+pub struct OptionalReadResource<'a, T: 'static + Send + Sync>(
+    RefCell<Option<Option<ResourceReadGuard<'a, T>>>>,
+);
+
+impl<'a, T: 'static + Send + Sync> Default for OptionalReadResource<'a, T> {
+    fn default() -> Self {
+        OptionalReadResource(RefCell::new(None))
+    }
+}
+
+/// Like `WriteResource`, but yields `None` instead of failing if the resource is missing.
+pub struct OptionalWriteResource<'a, T: 'static + Send + Sync>(
+    RefCell<Option<Option<ResourceWriteGuard<'a, T>>>>,
+);
+
+impl<'a, T: 'static + Send + Sync> Default for OptionalWriteResource<'a, T> {
+    fn default() -> Self {
+        OptionalWriteResource(RefCell::new(None))
+    }
+}
+
+impl<'a, T: 'static + Send + Sync> WorldLocker<'a> for OptionalReadResource<'a, T> {
+    type Handle = Option<ResourceReadGuard<'a, T>>;
+
+    fn id(&self) -> LockId {
+        LockId::Resource(TypeId::of::<T>())
+    }
+
+    fn access(&self) -> LockAccess {
+        LockAccess::Read
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(optional(world.read_resource_with::<T>(wait))?);
+        Ok(())
+    }
+
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        share(
+            self.0.borrow_mut().as_mut()?,
+            ResourceReadGuard::into_shared,
+        )
+    }
+
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>) {
+        *self.0.borrow_mut() = Some(Some(world.share_resource(lock)));
+    }
+
+    fn handle(self) -> Self::Handle {
+        self.0.into_inner().unwrap()
+    }
+}
+
+impl<'a, T: 'static + Send + Sync> WorldLocker<'a> for OptionalWriteResource<'a, T> {
+    type Handle = Option<ResourceWriteGuard<'a, T>>;
+
+    fn id(&self) -> LockId {
+        LockId::Resource(TypeId::of::<T>())
+    }
+
+    fn access(&self) -> LockAccess {
+        LockAccess::Write
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(optional(world.write_resource_with::<T>(wait))?);
+        Ok(())
+    }
+
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        None
+    }
+
+    fn lock_shared(&self, _: &'a World, _: SharedReadLock<'a>) {
+        unreachable!("write locks are never shared")
+    }
+
+    fn handle(self) -> Self::Handle {
+        self.0.into_inner().unwrap()
+    }
+}
+
+/// Like `ReadComponent`, but yields `None` instead of failing if the component is unregistered.
+pub struct OptionalReadComponent<'a, T: Component>(
+    RefCell<Option<Option<ComponentReadHandle<'a, T>>>>,
+);
+
+impl<'a, T: Component> Default for OptionalReadComponent<'a, T> {
+    fn default() -> Self {
+        OptionalReadComponent(RefCell::new(None))
+    }
+}
+
+/// Like `WriteComponent`, but yields `None` instead of failing if the component is unregistered.
+pub struct OptionalWriteComponent<'a, T: Component>(
+    RefCell<Option<Option<ComponentWriteHandle<'a, T>>>>,
+);
+
+impl<'a, T: Component> Default for OptionalWriteComponent<'a, T> {
+    fn default() -> Self {
+        OptionalWriteComponent(RefCell::new(None))
+    }
+}
+
+impl<'a, T: Component> WorldLocker<'a> for OptionalReadComponent<'a, T> {
+    type Handle = Option<ComponentReadHandle<'a, T>>;
+
+    fn id(&self) -> LockId {
+        LockId::Component(TypeId::of::<T>())
+    }
+
+    fn access(&self) -> LockAccess {
+        LockAccess::Read
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(optional(world.read_component_with::<T>(wait))?);
+        Ok(())
+    }
+
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        share(
+            self.0.borrow_mut().as_mut()?,
+            ComponentReadHandle::into_shared,
+        )
+    }
+
+    fn lock_shared(&self, world: &'a World, lock: SharedReadLock<'a>) {
+        *self.0.borrow_mut() = Some(Some(world.share_component(lock)));
+    }
+
+    fn handle(self) -> Self::Handle {
+        self.0.into_inner().unwrap()
+    }
+}
+
+impl<'a, T: Component> WorldLocker<'a> for OptionalWriteComponent<'a, T> {
+    type Handle = Option<ComponentWriteHandle<'a, T>>;
+
+    fn id(&self) -> LockId {
+        LockId::Component(TypeId::of::<T>())
+    }
+
+    fn access(&self) -> LockAccess {
+        LockAccess::Write
+    }
+
+    fn type_name(&self) -> &'static str {
+        type_name::<T>()
+    }
+
+    fn lock(&self, world: &'a World, wait: LockWait) -> Result<(), Error> {
+        *self.0.borrow_mut() = Some(optional(world.write_component_with::<T>(wait))?);
+        Ok(())
+    }
+
+    fn shared(&self) -> Option<SharedReadLock<'a>> {
+        None
+    }
+
+    fn lock_shared(&self, _: &'a World, _: SharedReadLock<'a>) {
+        unreachable!("write locks are never shared")
+    }
+
     fn handle(self) -> Self::Handle {
         self.0.into_inner().unwrap()
     }